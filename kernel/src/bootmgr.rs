@@ -0,0 +1,191 @@
+//! A small boot manager chain-loading other EFI images from the file system
+//! volumes or the TFTP server gem was booted from
+//!
+//! This lets a known good gem start test builds, for example to bisect a
+//! regression without reflashing the boot volume.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::time::Duration;
+use efi::{cstr16, efi_print, efi_print_colored, efi_println};
+use efi::{CStr16, CString16, EfiHandle, EfiResult, File, Image, LineEditor};
+use efi::Tftp;
+
+/// Directories searched for images on every volume
+const SEARCH_DIRECTORIES: &[&CStr16] = &[
+    cstr16!("\\"),
+    cstr16!("\\EFI\\BOOT"),
+    cstr16!("\\EFI\\gem"),
+];
+
+/// Where an image is loaded from
+#[derive(Clone, Copy)]
+pub enum Source {
+    /// The file system volume on the given device
+    Volume(EfiHandle),
+
+    /// The TFTP server gem was network booted from
+    Tftp(Tftp),
+}
+
+/// An image which can be chain-loaded
+pub struct BootEntry {
+    /// Where the image is loaded from
+    pub source: Source,
+
+    /// Path of the image on its source
+    pub path: String,
+}
+
+/// Returns `true` if `name` looks like an EFI image
+fn is_image(name: &str) -> bool {
+    name.to_ascii_lowercase().ends_with(".efi")
+}
+
+/// Find all images in the search directories of every volume and in the
+/// root directory of the TFTP server `tftp`
+pub fn entries(tftp: Option<Tftp>) -> Vec<BootEntry> {
+    let mut entries = Vec::new();
+
+    for device in efi::volumes().unwrap_or_default() {
+        let root = match File::open_volume(device) {
+            Ok(root) => root,
+            Err(_)   => continue,
+        };
+
+        for dir in SEARCH_DIRECTORIES {
+            let files = match root.open(dir).and_then(|mut dir| dir.entries()) {
+                Ok(files) => files,
+                Err(_)    => continue,
+            };
+
+            for file in files {
+                let name = file.name.to_string();
+                if file.is_directory() || !is_image(&name) { continue; }
+
+                entries.push(BootEntry {
+                    source: Source::Volume(device),
+                    path:   format!("{}\\{}",
+                        dir.to_string().trim_end_matches('\\'), name),
+                });
+            }
+        }
+    }
+
+    // Most TFTP servers can't list directories, images there can still be
+    // loaded by name
+    if let Some(tftp) = tftp {
+        let names = tftp.read_dir("").unwrap_or_default();
+
+        for name in names.into_iter().filter(|name| is_image(name)) {
+            entries.push(BootEntry { source: Source::Tftp(tftp), path: name });
+        }
+    }
+
+    entries
+}
+
+/// Load the image at `path` from `source`
+fn load(source: Source, path: &str) -> EfiResult<Image> {
+    match source {
+        Source::Volume(device) =>
+            Image::load_file(device, &efi::file_path(path)?),
+        Source::Tftp(tftp) =>
+            Image::load_buffer(&tftp.read_file(path)?, Some(tftp.device())),
+    }
+}
+
+/// Load the image at `path` from `source` and run it with `options` as its
+/// command line, returning once it exits
+pub fn chainload(source: Source, path: &str, options: &str)
+        -> EfiResult<()> {
+    let mut image = load(source, path)?;
+    image.set_load_options(&CString16::try_from(options)?)?;
+    image.start()
+}
+
+/// Run the image at `path` on the volume gem was loaded from, or from the
+/// TFTP server if gem was network booted
+pub fn chainload_path(path: &str, options: &str) -> EfiResult<()> {
+    let source = match efi::loaded_image() {
+        Ok(image) if File::open_volume(image.device()).is_ok() =>
+            Source::Volume(image.device()),
+        _ => Source::Tftp(Tftp::boot_server()?),
+    };
+
+    chainload(source, path, options)
+}
+
+/// Count down `seconds`, returning `true` if a key was pressed meanwhile
+fn countdown(seconds: u64) -> EfiResult<bool> {
+    for remaining in (1..=seconds).rev() {
+        efi_print!("\rPress any key to pick an image, booting in {} s ",
+            remaining);
+
+        if efi::read_key_timeout(Duration::from_secs(1))?.is_some() {
+            efi_println!();
+            return Ok(true);
+        }
+    }
+
+    efi_println!();
+    Ok(false)
+}
+
+/// Interactively pick an image and its load options and run it
+///
+/// With a `timeout` in seconds the menu is only shown if a key is pressed
+/// before it runs out. Returns when the user enters an empty selection or
+/// the timeout runs out, continuing the boot of gem itself.
+pub fn menu(timeout: Option<u64>) -> EfiResult<()> {
+    if let Some(seconds) = timeout {
+        if !countdown(seconds)? { return Ok(()); }
+    }
+
+    let mut editor = LineEditor::new(16);
+
+    // Only look for the TFTP server once, redrawing the menu must not wait
+    // on the network
+    let tftp = Tftp::boot_server().ok();
+
+    loop {
+        let entries = entries(tftp);
+
+        efi_println!("Boot images:");
+        for (idx, entry) in entries.iter().enumerate() {
+            let source = match entry.source {
+                Source::Volume(_) => "disk",
+                Source::Tftp(_)   => "tftp",
+            };
+
+            efi_println!("  {:>2}  {} {}", idx + 1, source, entry.path);
+        }
+
+        // Accept a number from the list, or a path to load from where gem
+        // came from
+        let selection = editor.read_line("Image (empty to continue)> ")?;
+        let selection = selection.trim();
+        if selection.is_empty() { return Ok(()); }
+
+        let options = editor.read_line("Options> ")?;
+
+        let ret = match selection.parse::<usize>() {
+            Ok(idx) if idx >= 1 && idx <= entries.len() => {
+                let entry = &entries[idx - 1];
+                chainload(entry.source, &entry.path, &options)
+            }
+            _ => chainload_path(selection, &options),
+        };
+
+        match ret {
+            Ok(()) => {
+                efi_println!("{} exited", selection);
+            }
+            Err(err) => {
+                efi_print_colored!(efi::Color::Yellow,
+                    "{} failed: {}\n", selection, err);
+            }
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! Kernel command line parsing
+//!
+//! The command line is a whitespace separated list of `key=value` options and
+//! flags. Values can be quoted with `"` or `'` to include whitespace, inside
+//! quotes `\"` or `\'` escapes the quote. Backslashes are otherwise taken
+//! literally, as UEFI paths use them as the separator. Every option must be
+//! declared in `PARAMETERS`, along with the kind of value it takes.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Kind of value a parameter takes
+#[derive(Clone, Copy, Debug)]
+pub enum ParamKind {
+    /// A flag without a value, like `nosmp`
+    Flag,
+
+    /// A decimal or `0x` prefixed hexadecimal integer
+    Integer,
+
+    /// Any string
+    String,
+
+    /// One string out of a fixed set
+    Choice(&'static [&'static str]),
+}
+
+/// Declaration of a command line parameter
+pub struct Param {
+    /// Name of the parameter as written on the command line
+    pub name: &'static str,
+
+    /// Kind of value the parameter takes
+    pub kind: ParamKind,
+
+    /// Short description of the parameter
+    pub description: &'static str,
+}
+
+/// All parameters understood by the kernel, subsystems add theirs here
+pub static PARAMETERS: &[Param] = &[
+    Param {
+        name:        "loglevel",
+        kind:        ParamKind::Choice(&["error", "warn", "info", "debug",
+                        "trace"]),
+        description: "Maximum level of log messages to display",
+    },
+    Param {
+        name:        "baud",
+        kind:        ParamKind::Integer,
+        description: "Baud rate of the serial ports",
+    },
+    Param {
+        name:        "nosmp",
+        kind:        ParamKind::Flag,
+        description: "Only use the bootstrap processor",
+    },
+    Param {
+        name:        "test",
+        kind:        ParamKind::String,
+        description: "Name of the test to run instead of booting",
+    },
+    Param {
+        name:        "initrd",
+        kind:        ParamKind::String,
+        description: "Path of the initial ramdisk on the boot volume or \
+                      TFTP server",
+    },
+    Param {
+        name:        "chainload",
+        kind:        ParamKind::String,
+        description: "Path of an EFI image to run before booting",
+    },
+    Param {
+        name:        "chainargs",
+        kind:        ParamKind::String,
+        description: "Command line passed to the `chainload` image",
+    },
+    Param {
+        name:        "bootmenu",
+        kind:        ParamKind::Flag,
+        description: "Pick EFI images to run from a menu before booting",
+    },
+    Param {
+        name:        "bootdelay",
+        kind:        ParamKind::Integer,
+        description: "Seconds to wait for a key before skipping `bootmenu`",
+    },
+    Param {
+        name:        "watchdog",
+        kind:        ParamKind::Integer,
+        description: "Reset if boot services aren't exited within this many \
+                      seconds",
+    },
+];
+
+/// A parsed parameter value
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    /// A flag which is present
+    Flag,
+
+    /// An integer value
+    Integer(u64),
+
+    /// A string or choice value
+    String(String),
+}
+
+/// Errors found while parsing the command line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CmdlineError {
+    /// A quote was opened but never closed
+    UnterminatedQuote,
+
+    /// The option is not declared in `PARAMETERS`
+    UnknownOption(String),
+
+    /// The option takes a value but none was given
+    MissingValue(&'static str),
+
+    /// The option is a flag but a value was given
+    UnexpectedValue(&'static str),
+
+    /// The value is not valid for the option
+    InvalidValue(&'static str, String),
+}
+
+impl fmt::Display for CmdlineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CmdlineError::UnterminatedQuote =>
+                write!(f, "unterminated quote"),
+            CmdlineError::UnknownOption(name) =>
+                write!(f, "unknown option `{}`", name),
+            CmdlineError::MissingValue(name) =>
+                write!(f, "option `{}` needs a value", name),
+            CmdlineError::UnexpectedValue(name) =>
+                write!(f, "option `{}` does not take a value", name),
+            CmdlineError::InvalidValue(name, value) =>
+                write!(f, "invalid value `{}` for option `{}`", value, name),
+        }
+    }
+}
+
+/// The parsed kernel command line
+#[derive(Default)]
+pub struct CommandLine {
+    /// Parsed options in command line order. Later options override earlier
+    /// ones.
+    values: Vec<(&'static str, Value)>,
+}
+
+impl CommandLine {
+    /// Parse `line` against `PARAMETERS`
+    ///
+    /// Invalid options are skipped and returned along with the options which
+    /// could be parsed
+    pub fn parse(line: &str) -> (Self, Vec<CmdlineError>) {
+        let mut cmdline = CommandLine::default();
+        let mut errors = Vec::new();
+
+        let mut tokens = match tokenize(line) {
+            Ok(tokens) => tokens,
+            Err(err) => {
+                errors.push(err);
+                return (cmdline, errors);
+            }
+        };
+
+        // The shell passes the image name as the first word, skip it
+        let is_image = |token: &String|
+            token.to_ascii_lowercase().ends_with(".efi");
+        if tokens.first().map_or(false, is_image) {
+            tokens.remove(0);
+        }
+
+        for token in tokens {
+            let (name, value) = match token.find('=') {
+                Some(idx) => (&token[..idx], Some(&token[idx + 1..])),
+                None      => (&token[..], None),
+            };
+
+            match parse_option(name, value) {
+                Ok(option) => cmdline.values.push(option),
+                Err(err)   => errors.push(err),
+            }
+        }
+
+        (cmdline, errors)
+    }
+
+    /// Get the value of the option `name`, if it was given
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values.iter().rev()
+            .find(|(param, _)| *param == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns `true` if the flag `name` was given
+    pub fn flag(&self, name: &str) -> bool {
+        self.get(name) == Some(&Value::Flag)
+    }
+
+    /// Get the integer value of the option `name`
+    pub fn integer(&self, name: &str) -> Option<u64> {
+        match self.get(name)? {
+            Value::Integer(val) => Some(*val),
+            _ => None,
+        }
+    }
+
+    /// Get the string value of the option `name`
+    pub fn string(&self, name: &str) -> Option<&str> {
+        match self.get(name)? {
+            Value::String(val) => Some(val),
+            _ => None,
+        }
+    }
+}
+
+/// Check the option `name` with `value` against its declaration
+fn parse_option(name: &str, value: Option<&str>)
+        -> Result<(&'static str, Value), CmdlineError> {
+    let param = PARAMETERS.iter()
+        .find(|param| param.name == name)
+        .ok_or_else(|| CmdlineError::UnknownOption(name.into()))?;
+
+    let value = match (param.kind, value) {
+        (ParamKind::Flag, None) => Value::Flag,
+        (ParamKind::Flag, Some(_)) =>
+            return Err(CmdlineError::UnexpectedValue(param.name)),
+        (_, None) => return Err(CmdlineError::MissingValue(param.name)),
+        (ParamKind::Integer, Some(value)) => {
+            let parsed = match value.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None      => value.parse(),
+            };
+
+            Value::Integer(parsed.map_err(|_|
+                CmdlineError::InvalidValue(param.name, value.into()))?)
+        }
+        (ParamKind::String, Some(value)) => Value::String(value.into()),
+        (ParamKind::Choice(choices), Some(value)) => {
+            if !choices.contains(&value) {
+                return Err(CmdlineError::InvalidValue(param.name,
+                    value.into()));
+            }
+
+            Value::String(value.into())
+        }
+    };
+
+    Ok((param.name, value))
+}
+
+/// Split `line` into whitespace separated words, handling quotes and
+/// escaped quotes inside them
+fn tokenize(line: &str) -> Result<Vec<String>, CmdlineError> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut in_token = false;
+    let mut quote = None;
+    let mut chars = line.chars().peekable();
+
+    while let Some(chr) = chars.next() {
+        match (chr, quote) {
+            // Only the closing quote can be escaped, any other backslash is
+            // a path separator
+            ('\\', Some(open)) if chars.peek() == Some(&open) => {
+                token.push(open);
+                chars.next();
+            }
+            ('"', None) | ('\'', None) => {
+                quote = Some(chr);
+                in_token = true;
+            }
+            (_, Some(open)) if chr == open => quote = None,
+            (_, None) if chr.is_whitespace() => {
+                if in_token {
+                    tokens.push(core::mem::take(&mut token));
+                    in_token = false;
+                }
+            }
+            _ => {
+                token.push(chr);
+                in_token = true;
+            }
+        }
+    }
+
+    if quote.is_some() { return Err(CmdlineError::UnterminatedQuote); }
+
+    if in_token { tokens.push(token); }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn tokenize_whitespace() {
+        assert_eq!(tokenize("  a b\tc  ").unwrap(), vec!["a", "b", "c"]);
+        assert!(tokenize("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn tokenize_quotes() {
+        assert_eq!(tokenize(r#"test="a b" x='c "d"'"#).unwrap(),
+            vec!["test=a b", r#"x=c "d""#]);
+        assert_eq!(tokenize(r#"test="say \"hi\"""#).unwrap(),
+            vec![r#"test=say "hi""#]);
+        assert_eq!(tokenize("''").unwrap(), vec![""]);
+        assert_eq!(tokenize("test=\"a b"),
+            Err(CmdlineError::UnterminatedQuote));
+    }
+
+    #[test]
+    fn tokenize_keeps_backslashes() {
+        assert_eq!(tokenize(r"initrd=\EFI\gem\initrd").unwrap(),
+            vec![r"initrd=\EFI\gem\initrd"]);
+        assert_eq!(tokenize(r#"chainload="\EFI\Boot\x.efi""#).unwrap(),
+            vec![r"chainload=\EFI\Boot\x.efi"]);
+    }
+
+    #[test]
+    fn parse_values() {
+        let (cmdline, errors) = CommandLine::parse(
+            r"gem.efi baud=0x1c200 nosmp loglevel=debug initrd=\gem\initrd");
+
+        assert!(errors.is_empty());
+        assert_eq!(cmdline.integer("baud"), Some(115200));
+        assert!(cmdline.flag("nosmp"));
+        assert_eq!(cmdline.string("loglevel"), Some("debug"));
+        assert_eq!(cmdline.string("initrd"), Some(r"\gem\initrd"));
+        assert_eq!(cmdline.get("test"), None);
+    }
+
+    #[test]
+    fn parse_later_options_override() {
+        let (cmdline, _) = CommandLine::parse("baud=9600 baud=115200");
+        assert_eq!(cmdline.integer("baud"), Some(115200));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let (cmdline, errors) = CommandLine::parse(
+            "bogus=1 baud nosmp=1 baud=fast loglevel=loud test=ok");
+
+        assert_eq!(errors, vec![
+            CmdlineError::UnknownOption("bogus".into()),
+            CmdlineError::MissingValue("baud"),
+            CmdlineError::UnexpectedValue("nosmp"),
+            CmdlineError::InvalidValue("baud", "fast".into()),
+            CmdlineError::InvalidValue("loglevel", "loud".into()),
+        ]);
+
+        // Valid options are kept despite the errors
+        assert_eq!(cmdline.string("test"), Some("ok"));
+        assert!(!cmdline.flag("nosmp"));
+    }
+}
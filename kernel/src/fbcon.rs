@@ -0,0 +1,451 @@
+//! Framebuffer text console used once the UEFI text output is gone
+//!
+//! Text is drawn with an embedded PSF bitmap font. `\n`, `\r`, tabs,
+//! backspaces and the ANSI SGR color escapes (`ESC[...m`) as well as
+//! `ESC[2J` and `ESC[H` are understood.
+
+use core::cell::UnsafeCell;
+use core::fmt::{Result, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use efi::{Framebuffer, PixelFormat};
+
+/// The embedded console font
+static FONT: &[u8] = include_bytes!("../fonts/gem8x16.psf");
+
+/// Number of columns a tab advances to
+const TAB_WIDTH: usize = 8;
+
+/// Maximum number of numeric parameters we keep from an escape sequence
+const MAX_ESCAPE_PARAMS: usize = 4;
+
+/// The 16 ANSI colors as `0xRRGGBB`, normal colors first then bright ones
+const PALETTE: [u32; 16] = [
+    0x000000, 0xaa0000, 0x00aa00, 0xaa5500,
+    0x0000aa, 0xaa00aa, 0x00aaaa, 0xaaaaaa,
+    0x555555, 0xff5555, 0x55ff55, 0xffff55,
+    0x5555ff, 0xff55ff, 0x55ffff, 0xffffff,
+];
+
+/// Default foreground color index in `PALETTE`
+const DEFAULT_FG: usize = 7;
+
+/// Default background color index in `PALETTE`
+const DEFAULT_BG: usize = 0;
+
+/// The console the kernel writes to after boot services are exited
+static FB_CONSOLE: GlobalConsole = GlobalConsole {
+    busy:    AtomicBool::new(false),
+    console: UnsafeCell::new(None),
+};
+
+/// The global framebuffer console, only accessed while `busy` is held
+struct GlobalConsole {
+    /// Set while someone is using `console`
+    busy: AtomicBool,
+
+    /// The console, if one was set up
+    console: UnsafeCell<Option<FramebufferConsole>>,
+}
+
+unsafe impl Sync for GlobalConsole {}
+
+impl GlobalConsole {
+    /// Run `func` on the console, unless it is already in use
+    ///
+    /// Waiting for the console to be released would deadlock when the panic
+    /// handler interrupts a write, thus nested users are skipped instead.
+    fn with<R>(&self, func: impl FnOnce(&mut Option<FramebufferConsole>) -> R)
+            -> Option<R> {
+        if self.busy.compare_exchange(false, true, Ordering::Acquire,
+                Ordering::Relaxed).is_err() {
+            return None;
+        }
+
+        let ret = func(unsafe { &mut *self.console.get() });
+
+        self.busy.store(false, Ordering::Release);
+        Some(ret)
+    }
+}
+
+/// A parsed PSF1 or PSF2 font
+struct Font {
+    /// Glyph bitmaps, `bytes_per_glyph` bytes per glyph
+    glyphs: &'static [u8],
+
+    /// Number of glyphs in the font
+    count: usize,
+
+    /// Size of a single glyph in bytes
+    bytes_per_glyph: usize,
+
+    /// Width of a glyph in pixels
+    width: usize,
+
+    /// Height of a glyph in pixels
+    height: usize,
+}
+
+impl Font {
+    /// Parse a PSF1 or PSF2 font from `data`
+    fn parse(data: &'static [u8]) -> Option<Self> {
+        // Read a little endian u32 at `off`
+        let u32_at = |off: usize| -> Option<usize> {
+            let bytes = data.get(off..off + 4)?;
+            Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+                as usize)
+        };
+
+        let (header_size, count, bytes_per_glyph, width, height) =
+            if data.get(0..2)? == [0x36, 0x04] {
+                // PSF1: 8 pixels wide, 256 or 512 glyphs
+                let count = if data[2] & 1 != 0 { 512 } else { 256 };
+                let height = *data.get(3)? as usize;
+                (4, count, height, 8, height)
+            } else if u32_at(0)? == 0x864ab572 {
+                // PSF2: everything is described in the header
+                (u32_at(8)?, u32_at(16)?, u32_at(20)?, u32_at(28)?,
+                    u32_at(24)?)
+            } else {
+                return None;
+            };
+
+        let glyphs = data.get(header_size..header_size +
+            count * bytes_per_glyph)?;
+
+        Some(Font { glyphs, count, bytes_per_glyph, width, height })
+    }
+
+    /// Get the bitmap of the glyph for `chr`, falling back to `?` for
+    /// characters the font doesn't have
+    fn glyph(&self, chr: char) -> &[u8] {
+        let idx = if (chr as usize) < self.count { chr as usize } else {
+            b'?' as usize
+        };
+
+        &self.glyphs[idx * self.bytes_per_glyph..
+            (idx + 1) * self.bytes_per_glyph]
+    }
+}
+
+/// State of the escape sequence parser
+#[derive(Clone, Copy)]
+enum Escape {
+    /// Not in an escape sequence
+    None,
+
+    /// Got `ESC`
+    Esc,
+
+    /// Got `ESC[`, collecting numeric parameters
+    Csi {
+        params: [usize; MAX_ESCAPE_PARAMS],
+        count:  usize,
+    },
+}
+
+/// A text console drawing to a linear framebuffer
+pub struct FramebufferConsole {
+    /// The framebuffer we draw to
+    fb: Framebuffer,
+
+    /// The font used to draw characters
+    font: Font,
+
+    /// Number of text columns on screen
+    columns: usize,
+
+    /// Number of text rows on screen
+    rows: usize,
+
+    /// Column of the cursor
+    column: usize,
+
+    /// Row of the cursor
+    row: usize,
+
+    /// Current foreground color index in `PALETTE`
+    fg: usize,
+
+    /// Current background color index in `PALETTE`
+    bg: usize,
+
+    /// Escape sequence parser state
+    escape: Escape,
+}
+
+impl FramebufferConsole {
+    /// Create a new console on `fb` and clear the screen
+    ///
+    /// Returns `None` if the framebuffer has no usable pixel layout.
+    ///
+    /// # Safety
+    ///
+    /// The caller must make sure `fb` describes mapped memory which is not
+    /// used for anything else.
+    pub unsafe fn new(fb: Framebuffer) -> Option<Self> {
+        if fb.format == PixelFormat::BltOnly { return None; }
+
+        let font = Font::parse(FONT)?;
+
+        let mut console = FramebufferConsole {
+            columns: fb.width as usize / font.width,
+            rows:    fb.height as usize / font.height,
+            fb,
+            font,
+            column:  0,
+            row:     0,
+            fg:      DEFAULT_FG,
+            bg:      DEFAULT_BG,
+            escape:  Escape::None,
+        };
+
+        if console.columns == 0 || console.rows == 0 { return None; }
+
+        console.clear();
+
+        Some(console)
+    }
+
+    /// Convert a `0xRRGGBB` color into the framebuffer's pixel layout
+    fn pixel(&self, rgb: u32) -> u32 {
+        let (r, g, b) = ((rgb >> 16) & 0xff, (rgb >> 8) & 0xff, rgb & 0xff);
+
+        match self.fb.format {
+            PixelFormat::Rgb => r | (g << 8) | (b << 16),
+            PixelFormat::Bgr => b | (g << 8) | (r << 16),
+            PixelFormat::Bitmask(mask) => {
+                // Scale each 8-bit channel to the size of its mask
+                let channel = |val: u32, mask: u32| -> u32 {
+                    if mask == 0 { return 0; }
+                    let bits = mask.count_ones().min(8);
+                    ((val >> (8 - bits)) << mask.trailing_zeros()) & mask
+                };
+
+                channel(r, mask.red_mask) | channel(g, mask.green_mask) |
+                    channel(b, mask.blue_mask)
+            }
+            PixelFormat::BltOnly => 0,
+        }
+    }
+
+    /// Fill the pixel rectangle at `x`, `y` of `width` by `height` pixels
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize,
+            rgb: u32) {
+        let pixel = self.pixel(rgb);
+        let base = self.fb.base as *mut u32;
+
+        for yy in y..y + height {
+            for xx in x..x + width {
+                unsafe {
+                    base.add(yy * self.fb.stride as usize + xx)
+                        .write_volatile(pixel);
+                }
+            }
+        }
+    }
+
+    /// Clear the screen to the background color and home the cursor
+    fn clear(&mut self) {
+        let (width, height) = (self.fb.width as usize, self.fb.height as usize);
+        self.fill(0, 0, width, height, PALETTE[self.bg]);
+
+        self.column = 0;
+        self.row = 0;
+    }
+
+    /// Draw `chr` at the cursor position, without moving the cursor
+    fn draw(&mut self, chr: char) {
+        let fg = self.pixel(PALETTE[self.fg]);
+        let bg = self.pixel(PALETTE[self.bg]);
+
+        let x = self.column * self.font.width;
+        let y = self.row * self.font.height;
+        let bytes_per_row = (self.font.width + 7) / 8;
+        let base = self.fb.base as *mut u32;
+
+        let glyph = self.font.glyph(chr);
+
+        for gy in 0..self.font.height {
+            let row = &glyph[gy * bytes_per_row..(gy + 1) * bytes_per_row];
+
+            for gx in 0..self.font.width {
+                let set = row[gx / 8] & (0x80 >> (gx % 8)) != 0;
+
+                unsafe {
+                    base.add((y + gy) * self.fb.stride as usize + x + gx)
+                        .write_volatile(if set { fg } else { bg });
+                }
+            }
+        }
+    }
+
+    /// Move the cursor to the start of the next line, scrolling if needed
+    fn newline(&mut self) {
+        self.column = 0;
+
+        if self.row + 1 < self.rows {
+            self.row += 1;
+            return;
+        }
+
+        // Move everything up by one text row
+        let stride = self.fb.stride as usize;
+        let line = stride * self.font.height;
+        let base = self.fb.base as *mut u32;
+
+        unsafe {
+            core::ptr::copy(base.add(line), base,
+                line * (self.rows - 1));
+        }
+
+        // Clear the now free last row
+        let (width, height) = (self.fb.width as usize, self.font.height);
+        self.fill(0, (self.rows - 1) * height, width, height,
+            PALETTE[self.bg]);
+    }
+
+    /// Apply the parameters of a `ESC[...m` sequence
+    fn set_graphics_rendition(&mut self, params: &[usize]) {
+        // No parameters is the same as a reset
+        if params.is_empty() {
+            self.fg = DEFAULT_FG;
+            self.bg = DEFAULT_BG;
+        }
+
+        for &param in params {
+            match param {
+                0 => {
+                    self.fg = DEFAULT_FG;
+                    self.bg = DEFAULT_BG;
+                }
+                1       => self.fg |= 8,
+                30..=37 => self.fg = param - 30,
+                39      => self.fg = DEFAULT_FG,
+                40..=47 => self.bg = param - 40,
+                49      => self.bg = DEFAULT_BG,
+                90..=97 => self.fg = param - 90 + 8,
+                100..=107 => self.bg = param - 100 + 8,
+                _ => {}
+            }
+        }
+    }
+
+    /// Handle the final character `chr` of a `ESC[` sequence
+    fn finish_csi(&mut self, chr: char, params: &[usize]) {
+        match chr {
+            'm' => self.set_graphics_rendition(params),
+            'J' if params.first() == Some(&2) => self.clear(),
+            'H' => {
+                // Parameters are 1-based row and column
+                let row = params.first().copied().unwrap_or(1).max(1) - 1;
+                let col = params.get(1).copied().unwrap_or(1).max(1) - 1;
+                self.row = row.min(self.rows - 1);
+                self.column = col.min(self.columns - 1);
+            }
+            _ => {}
+        }
+    }
+
+    /// Write a single character to the console
+    fn put_char(&mut self, chr: char) {
+        match self.escape {
+            Escape::None => {}
+            Escape::Esc => {
+                self.escape = if chr == '[' {
+                    Escape::Csi { params: [0; MAX_ESCAPE_PARAMS], count: 0 }
+                } else {
+                    Escape::None
+                };
+                return;
+            }
+            Escape::Csi { mut params, mut count } => {
+                match chr {
+                    '0'..='9' => {
+                        // The first digit starts the first parameter
+                        if count == 0 { count = 1; }
+
+                        if count <= MAX_ESCAPE_PARAMS {
+                            let param = &mut params[count - 1];
+                            *param = param.saturating_mul(10)
+                                .saturating_add(chr as usize - '0' as usize);
+                        }
+
+                        self.escape = Escape::Csi { params, count };
+                    }
+                    ';' => {
+                        // An empty first parameter still counts
+                        count = count.max(1) + 1;
+                        self.escape = Escape::Csi { params, count };
+                    }
+                    _ => {
+                        self.escape = Escape::None;
+                        let count = count.min(MAX_ESCAPE_PARAMS);
+                        self.finish_csi(chr, &params[..count]);
+                    }
+                }
+                return;
+            }
+        }
+
+        match chr {
+            '\x1b' => self.escape = Escape::Esc,
+            '\n' => self.newline(),
+            '\r' => self.column = 0,
+            '\x08' => self.column = self.column.saturating_sub(1),
+            '\t' => {
+                let next = (self.column / TAB_WIDTH + 1) * TAB_WIDTH;
+
+                if next >= self.columns {
+                    self.newline();
+                } else {
+                    self.column = next;
+                }
+            }
+            _ => {
+                if self.column >= self.columns { self.newline(); }
+
+                self.draw(chr);
+                self.column += 1;
+            }
+        }
+    }
+}
+
+impl Write for FramebufferConsole {
+    fn write_str(&mut self, string: &str) -> Result {
+        for chr in string.chars() {
+            self.put_char(chr);
+        }
+
+        Ok(())
+    }
+}
+
+/// Write `string` to the global framebuffer console, if there is one
+fn write_global(string: &str) {
+    FB_CONSOLE.with(|console| {
+        if let Some(console) = console {
+            let _ = console.write_str(string);
+        }
+    });
+}
+
+/// Set up the global framebuffer console on `fb` and route all console
+/// output to it once boot services are gone
+///
+/// # Safety
+///
+/// The caller must make sure `fb` describes mapped memory which is not used
+/// for anything else.
+pub unsafe fn init(fb: Framebuffer) -> bool {
+    let ready = FB_CONSOLE.with(|console| {
+        *console = FramebufferConsole::new(fb);
+        console.is_some()
+    });
+
+    if ready != Some(true) { return false; }
+
+    efi::register_console_fallback(write_global);
+    true
+}
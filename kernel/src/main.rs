@@ -1,32 +1,176 @@
 #![feature(asm)]
 #![feature(llvm_asm)]
 #![feature(abi_efiapi)]
-#![no_std]
-#![no_main]
+// Unit tests run on the host, with std and its test harness
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
 
+extern crate alloc;
+
+mod bootmgr;
+mod cmdline;
+#[cfg(not(test))]
 mod core_requirements;
+mod fbcon;
 
 use serial::SerialPort;
-use core::panic::PanicInfo;
+use alloc::vec::Vec;
+use core::time::Duration;
 #[macro_use] use efi::*;
 
+/// All allocations are served from the firmware pool until boot services are
+/// exited
+#[cfg(not(test))]
+#[global_allocator]
+static GLOBAL_ALLOCATOR: PoolAllocator = PoolAllocator;
+
+#[cfg(not(test))]
 #[panic_handler]
-fn panic(info: &PanicInfo) -> ! {
-    efi_print!("{}", info);
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    efi::with_color(Console::Err, Color::LightRed,
+        || { efi_eprintln!("{}", info); });
+
+    // Power off cleanly, this only comes back if runtime services are not
+    // available
+    efi::reset_system(EfiResetType::Shutdown, EfiStatus::ABORTED);
+
     loop {}
 }
 
+/// Read the file at `path` from the boot volume, or from the TFTP server if
+/// gem was network booted
+fn load_boot_file(path: &str) -> EfiResult<Vec<u8>> {
+    efi::read_file(path).or_else(|_| efi::tftp_read_file(path))
+}
+
 #[no_mangle]
 extern fn efi_main(image: EfiHandle, sys_t: *mut EfiSystemTable) -> EfiStatus {
     // TODO: MAKE IT NOT PAGE FAULT >:(a
 
+    // Check the firmware tables before registering them, nothing calls
+    // through them until they passed
+    let revision = match unsafe { efi::validate_system_table(sys_t) } {
+        Ok(revision) => revision,
+        Err(err) => {
+            // The console is only reached through the system table, so the
+            // error can only be reported if that table itself is intact
+            if unsafe { efi::validate_table(sys_t) }.is_ok() {
+                unsafe { register_system_table(sys_t); }
+                efi_print_colored!(Color::LightRed,
+                    "Unusable firmware: {}\n", err);
+            }
+            return EfiError::from(err).status();
+        }
+    };
+
     let st = unsafe { &mut *sys_t };
 
     unsafe { register_system_table(sys_t); }
+    efi::register_image_handle(image);
+
+    // The firmware resets the machine 5 minutes after starting us unless the
+    // watchdog is disabled, it is only rearmed for unattended runs
+    let _ = efi::disable_watchdog();
+
+    efi_println!("Firmware {} revision {:#x}, UEFI {}",
+        st.firmware_vendor(), st.firmware_revision, revision);
+
+    let mut command_line = alloc::string::String::new();
+
+    if let Ok(loaded_image) = efi::loaded_image() {
+        command_line = loaded_image.command_line();
+
+        efi_println!("gem loaded at {:#x} ({} bytes), options \"{}\"",
+            loaded_image.image_base(),
+            loaded_image.image_size(),
+            command_line
+        );
+    }
+
+    let (cmdline, errors) = cmdline::CommandLine::parse(&command_line);
 
-    efi::get_memory_map();
+    if !errors.is_empty() {
+        for error in &errors {
+            efi_print_colored!(Color::Yellow, "Command line: {}\n", error);
+        }
 
-    //unsafe { ((*(*sys_t).boot_services).exit_boot_services)(image, 0); }
+        efi_println!("Available options:");
+        for param in cmdline::PARAMETERS {
+            efi_println!("  {:<10} {}", param.name, param.description);
+        }
+    }
 
-    EfiStatus::EfiSuccess
+    if let Some(seconds) = cmdline.integer("watchdog") {
+        if let Err(err) = efi::set_watchdog(Duration::from_secs(seconds),
+                efi::WATCHDOG_CODE) {
+            efi_print_colored!(Color::Yellow,
+                "Could not arm the watchdog: {}\n", err);
+        }
+    }
+
+    // Run other images first when acting as a boot manager, gem only keeps
+    // booting once they exit
+    if let Some(path) = cmdline.string("chainload") {
+        let options = cmdline.string("chainargs").unwrap_or("");
+
+        if let Err(err) = bootmgr::chainload_path(path, options) {
+            efi_print_colored!(Color::Yellow,
+                "Could not run {}: {}\n", path, err);
+        }
+    }
+
+    if cmdline.flag("bootmenu") {
+        if let Err(err) = bootmgr::menu(cmdline.integer("bootdelay")) {
+            efi_print_colored!(Color::Yellow, "Boot menu failed: {}\n", err);
+        }
+    }
+
+    // Load the initrd while the firmware can still read files for us
+    let _initrd = cmdline.string("initrd").and_then(|path| {
+        match load_boot_file(path) {
+            Ok(data) => {
+                efi_println!("Loaded initrd {} ({} bytes)", path, data.len());
+                Some(data)
+            }
+            Err(err) => {
+                efi_print_colored!(Color::Yellow,
+                    "Could not load initrd {}: {}\n", path, err);
+                None
+            }
+        }
+    });
+
+    let memory_map = efi::get_memory_map()
+        .expect("Could not get the memory map");
+
+    for entry in &memory_map {
+        efi_println!("{:016x} {:016x} {:?}",
+            entry.physical_start,
+            entry.size(),
+            entry.typ
+        );
+    }
+
+    efi_println!("Total bytes free {}", memory_map.free_memory());
+
+    // Grab the framebuffer while the firmware can still tell us about it
+    let framebuffer = efi::framebuffer().ok();
+
+    if let Some(fb) = &framebuffer {
+        efi_println!("Framebuffer {:#x} {}x{} {:?}",
+            fb.base, fb.width, fb.height, fb.format);
+    }
+
+    let _memory_map = efi::exit_boot_services(image)
+        .expect("Could not exit boot services");
+
+    // The UEFI console is gone, keep printing on the framebuffer
+    if let Some(fb) = framebuffer {
+        if unsafe { fbcon::init(fb) } {
+            efi_println!("Boot services exited");
+        }
+    }
+
+    // There is no firmware to return to anymore
+    loop {}
 }
@@ -0,0 +1,63 @@
+//! Allocator backed by the UEFI boot services pool
+//!
+//! While boot services are alive every allocation is served from the pool as
+//! `LoaderData`. `exit_boot_services()` is the handoff point: once it
+//! returns, new allocations fail and frees become no-ops, leaving everything
+//! allocated so far owned by the kernel.
+
+use core::alloc::{GlobalAlloc, Layout};
+use crate::{allocate_pool, free_pool, EfiMemoryType};
+
+/// Alignment guaranteed by `AllocatePool()` for all allocations
+const POOL_ALIGNMENT: usize = 8;
+
+/// A `GlobalAlloc` implementation on top of `AllocatePool()`/`FreePool()`
+///
+/// Images running on the firmware declare it as their global allocator:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static GLOBAL_ALLOCATOR: efi::PoolAllocator = efi::PoolAllocator;
+/// ```
+pub struct PoolAllocator;
+
+unsafe impl GlobalAlloc for PoolAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // The pool already satisfies small alignments
+        if layout.align() <= POOL_ALIGNMENT {
+            return allocate_pool(EfiMemoryType::LoaderData, layout.size())
+                .unwrap_or(core::ptr::null_mut());
+        }
+
+        // Allocate enough room to align the buffer up, the pointer returned
+        // by the pool is stashed right before the aligned buffer
+        let size = match layout.size().checked_add(layout.align()) {
+            Some(size) => size,
+            None       => return core::ptr::null_mut(),
+        };
+
+        let raw = match allocate_pool(EfiMemoryType::LoaderData, size) {
+            Ok(raw) => raw,
+            Err(_)  => return core::ptr::null_mut(),
+        };
+
+        // As `raw` is 8-byte aligned and the alignment is larger than that,
+        // there are always at least 8 bytes before the aligned pointer
+        let aligned = ((raw as usize + layout.align()) &
+            !(layout.align() - 1)) as *mut u8;
+        (aligned as *mut *mut u8).sub(1).write(raw);
+
+        aligned
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // Get back the pointer which came from the pool
+        let raw = if layout.align() <= POOL_ALIGNMENT {
+            ptr
+        } else {
+            (ptr as *mut *mut u8).sub(1).read()
+        };
+
+        let _ = free_pool(raw);
+    }
+}
@@ -0,0 +1,292 @@
+//! Lookup of the configuration tables published in the EFI System Table
+
+use core::ffi::c_void;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use crate::{system_table, EfiConfigurationTable, EfiGuid, EfiSystemTable};
+use crate::{EfiTableHeader, MemoryMap};
+
+/// GUID of the ACPI 1.0 RSDP
+pub const ACPI_TABLE_GUID: EfiGuid = EfiGuid::new(
+    0xeb9d2d30, 0x2d88, 0x11d3,
+    [0x9a, 0x16, 0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d]);
+
+/// GUID of the ACPI 2.0+ RSDP
+pub const ACPI_20_TABLE_GUID: EfiGuid = EfiGuid::new(
+    0x8868e871, 0xe4f1, 0x11d3,
+    [0xbc, 0x22, 0x00, 0x80, 0xc7, 0x3c, 0x88, 0x81]);
+
+/// GUID of the 32-bit SMBIOS entry point
+pub const SMBIOS_TABLE_GUID: EfiGuid = EfiGuid::new(
+    0xeb9d2d31, 0x2d88, 0x11d3,
+    [0x9a, 0x16, 0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d]);
+
+/// GUID of the 64-bit SMBIOS 3.0 entry point
+pub const SMBIOS3_TABLE_GUID: EfiGuid = EfiGuid::new(
+    0xf2fd1544, 0x9794, 0x4a2c,
+    [0x99, 0x2e, 0xe5, 0xbb, 0xcf, 0x20, 0xe3, 0x94]);
+
+/// GUID of the DXE services table
+pub const DXE_SERVICES_TABLE_GUID: EfiGuid = EfiGuid::new(
+    0x05ad34ba, 0x6f02, 0x4214,
+    [0x95, 0x2e, 0x4d, 0xa0, 0x39, 0x8e, 0x2b, 0xb9]);
+
+/// GUID of the flattened device tree blob
+pub const DEVICE_TREE_TABLE_GUID: EfiGuid = EfiGuid::new(
+    0xb1b621d5, 0xf19c, 0x41a5,
+    [0x83, 0x0b, 0xd9, 0x15, 0x2c, 0x69, 0xaa, 0xe0]);
+
+/// ACPI 1.0 Root System Description Pointer
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct AcpiRsdp {
+    /// "RSD PTR "
+    pub signature: [u8; 8],
+
+    /// Makes the sum of the bytes of this structure zero
+    pub checksum: u8,
+
+    /// OEM supplied string identifying the OEM
+    pub oem_id: [u8; 6],
+
+    /// 0 for ACPI 1.0, 2 for ACPI 2.0 and later
+    pub revision: u8,
+
+    /// Physical address of the RSDT
+    pub rsdt_address: u32,
+}
+
+/// ACPI 2.0+ Root System Description Pointer
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct Acpi20Rsdp {
+    /// The ACPI 1.0 part of the structure
+    pub rsdp: AcpiRsdp,
+
+    /// Length of the whole structure in bytes
+    pub length: u32,
+
+    /// Physical address of the XSDT
+    pub xsdt_address: u64,
+
+    /// Makes the sum of the bytes of the whole structure zero
+    pub extended_checksum: u8,
+
+    pub reserved: [u8; 3],
+}
+
+/// SMBIOS 2.1 32-bit entry point structure
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct SmbiosEntryPoint {
+    /// "_SM_"
+    pub anchor: [u8; 4],
+
+    /// Makes the sum of the bytes of the entry point zero
+    pub checksum: u8,
+
+    /// Length of the entry point in bytes
+    pub length: u8,
+
+    pub major_version: u8,
+    pub minor_version: u8,
+
+    /// Size of the largest SMBIOS structure
+    pub max_structure_size: u16,
+
+    pub entry_point_revision: u8,
+    pub formatted_area: [u8; 5],
+
+    /// "_DMI_"
+    pub intermediate_anchor: [u8; 5],
+
+    /// Makes the sum of the bytes of the intermediate entry point zero
+    pub intermediate_checksum: u8,
+
+    /// Length of the structure table in bytes
+    pub table_length: u16,
+
+    /// Physical address of the structure table
+    pub table_address: u32,
+
+    /// Number of structures in the structure table
+    pub number_of_structures: u16,
+
+    pub bcd_revision: u8,
+}
+
+/// SMBIOS 3.0 64-bit entry point structure
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct Smbios3EntryPoint {
+    /// "_SM3_"
+    pub anchor: [u8; 5],
+
+    /// Makes the sum of the bytes of the entry point zero
+    pub checksum: u8,
+
+    /// Length of the entry point in bytes
+    pub length: u8,
+
+    pub major_version: u8,
+    pub minor_version: u8,
+    pub docrev: u8,
+    pub entry_point_revision: u8,
+    pub reserved: u8,
+
+    /// Maximum size of the structure table in bytes
+    pub table_max_size: u32,
+
+    /// Physical address of the structure table
+    pub table_address: u64,
+}
+
+/// The configuration table array of the system table, saved when it is
+/// registered and again when boot services are exited
+static EFI_CONFIGURATION_TABLES: AtomicPtr<EfiConfigurationTable> =
+    AtomicPtr::new(core::ptr::null_mut());
+
+/// Number of entries in `EFI_CONFIGURATION_TABLES`
+static EFI_CONFIGURATION_TABLE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A configuration table identified by its GUID
+///
+/// Each variant holds a pointer to the table as published by the firmware.
+/// These are physical addresses, `set_virtual_address_map()` doesn't convert
+/// them. Tables in runtime regions are found at the address
+/// `MemoryMap::runtime_virtual_address()` gives once the map is switched.
+#[derive(Clone, Copy, Debug)]
+pub enum ConfigurationTable {
+    /// ACPI 1.0 Root System Description Pointer
+    Acpi10(*const AcpiRsdp),
+
+    /// ACPI 2.0+ Root System Description Pointer
+    Acpi20(*const Acpi20Rsdp),
+
+    /// SMBIOS entry point structure
+    Smbios(*const SmbiosEntryPoint),
+
+    /// SMBIOS 3.0 64-bit entry point structure
+    Smbios3(*const Smbios3EntryPoint),
+
+    /// DXE services table
+    DxeServices(*const EfiTableHeader),
+
+    /// Flattened device tree blob
+    DeviceTree(*const u8),
+
+    /// A table we don't know about
+    Unknown(EfiGuid, *const c_void),
+}
+
+impl ConfigurationTable {
+    /// Get the address of the table
+    pub fn address(&self) -> usize {
+        use ConfigurationTable::*;
+        match *self {
+            Acpi10(ptr)      => ptr as usize,
+            Acpi20(ptr)      => ptr as usize,
+            Smbios(ptr)      => ptr as usize,
+            Smbios3(ptr)     => ptr as usize,
+            DxeServices(ptr) => ptr as usize,
+            DeviceTree(ptr)  => ptr as usize,
+            Unknown(_, ptr)  => ptr as usize,
+        }
+    }
+}
+
+impl From<&EfiConfigurationTable> for ConfigurationTable {
+    fn from(table: &EfiConfigurationTable) -> Self {
+        use ConfigurationTable::*;
+        let addr = table.table;
+        match table.guid {
+            ACPI_TABLE_GUID         => Acpi10(addr as *const _),
+            ACPI_20_TABLE_GUID      => Acpi20(addr as *const _),
+            SMBIOS_TABLE_GUID       => Smbios(addr as *const _),
+            SMBIOS3_TABLE_GUID      => Smbios3(addr as *const _),
+            DXE_SERVICES_TABLE_GUID => DxeServices(addr as *const _),
+            DEVICE_TREE_TABLE_GUID  => DeviceTree(addr as *const _),
+            guid                    => Unknown(guid, addr as *const _),
+        }
+    }
+}
+
+impl EfiSystemTable {
+    /// Get the raw configuration table entries
+    pub fn configuration_tables(&self) -> &[EfiConfigurationTable] {
+        if self.tables.is_null() { return &[]; }
+
+        unsafe {
+            core::slice::from_raw_parts(self.tables, self.number_of_tables)
+        }
+    }
+}
+
+/// Save the configuration table array of `st`, so the tables can still be
+/// found once boot services are exited and the system table is unregistered
+pub(crate) fn save_configuration_tables(st: &EfiSystemTable) {
+    EFI_CONFIGURATION_TABLES.store(st.tables as *mut EfiConfigurationTable,
+        Ordering::SeqCst);
+    EFI_CONFIGURATION_TABLE_COUNT.store(st.number_of_tables,
+        Ordering::SeqCst);
+}
+
+/// Switch the saved configuration table array to the virtual address `map`
+/// assigns it, once `SetVirtualAddressMap()` succeeded
+///
+/// The array is left alone if it's not in a runtime region. The addresses
+/// in the entries are kept as published.
+pub(crate) fn relocate_configuration_tables(map: &MemoryMap) {
+    let tables = EFI_CONFIGURATION_TABLES.load(Ordering::SeqCst);
+    if tables.is_null() { return; }
+
+    if let Some(virt) = map.runtime_virtual_address(tables as u64) {
+        EFI_CONFIGURATION_TABLES.store(virt as *mut EfiConfigurationTable,
+            Ordering::SeqCst);
+    }
+}
+
+/// Get the configuration tables of the registered system table, or the ones
+/// saved when boot services were exited
+fn raw_configuration_tables() -> &'static [EfiConfigurationTable] {
+    if let Ok(st) = system_table() { return st.configuration_tables(); }
+
+    let tables = EFI_CONFIGURATION_TABLES.load(Ordering::SeqCst);
+    if tables.is_null() { return &[]; }
+
+    unsafe {
+        core::slice::from_raw_parts(tables,
+            EFI_CONFIGURATION_TABLE_COUNT.load(Ordering::SeqCst))
+    }
+}
+
+/// Get an iterator over the typed configuration tables of the registered
+/// system table
+///
+/// The tables live in runtime memory, thus they are still available after
+/// boot services have been exited. The iterator is only empty if no system
+/// table was ever registered.
+pub fn configuration_tables() -> impl Iterator<Item = ConfigurationTable> {
+    raw_configuration_tables().iter().map(ConfigurationTable::from)
+}
+
+/// Look up a configuration table by its GUID
+pub fn find_configuration_table(guid: &EfiGuid) -> Option<ConfigurationTable> {
+    raw_configuration_tables()
+        .iter()
+        .find(|table| table.guid == *guid)
+        .map(ConfigurationTable::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::mem::size_of;
+
+    #[test]
+    fn entry_point_sizes() {
+        assert_eq!(size_of::<AcpiRsdp>(), 20);
+        assert_eq!(size_of::<Acpi20Rsdp>(), 36);
+        assert_eq!(size_of::<SmbiosEntryPoint>(), 31);
+        assert_eq!(size_of::<Smbios3EntryPoint>(), 24);
+    }
+}
@@ -0,0 +1,219 @@
+//! Text output control for the UEFI console: modes, colors and cursor
+
+use crate::{system_table, EfiError, EfiResult, EfiSimpleTextOutputProtocol};
+use crate::{Console, EfiStatus};
+
+/// Text colors supported by `SetAttribute()`
+///
+/// Only the first 8 colors can be used as a background color
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Color {
+    Black,
+    Blue,
+    Green,
+    Cyan,
+    Red,
+    Magenta,
+    Brown,
+    LightGray,
+    DarkGray,
+    LightBlue,
+    LightGreen,
+    LightCyan,
+    LightRed,
+    LightMagenta,
+    Yellow,
+    White,
+}
+
+impl From<u8> for Color {
+    fn from(val: u8) -> Self {
+        use Color::*;
+        match val & 0xf {
+            0x0 => Black,
+            0x1 => Blue,
+            0x2 => Green,
+            0x3 => Cyan,
+            0x4 => Red,
+            0x5 => Magenta,
+            0x6 => Brown,
+            0x7 => LightGray,
+            0x8 => DarkGray,
+            0x9 => LightBlue,
+            0xa => LightGreen,
+            0xb => LightCyan,
+            0xc => LightRed,
+            0xd => LightMagenta,
+            0xe => Yellow,
+            _   => White,
+        }
+    }
+}
+
+/// Dimensions of a text mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextMode {
+    /// The mode number to pass to `set_mode()`
+    pub number: usize,
+
+    /// Number of columns
+    pub columns: usize,
+
+    /// Number of rows
+    pub rows: usize,
+}
+
+/// Get the console output protocol
+fn console_out() -> EfiResult<&'static EfiSimpleTextOutputProtocol> {
+    text_output(Console::Out)
+}
+
+/// Get the text output protocol of `console`, the console output device for
+/// `Console::Both`
+///
+/// Fails with `EfiStatus::UNSUPPORTED` if the firmware doesn't provide the
+/// device
+fn text_output(console: Console)
+        -> EfiResult<&'static EfiSimpleTextOutputProtocol> {
+    let st = system_table()?;
+
+    let out = match console {
+        Console::Err => st.console_err,
+        _            => st.console_out,
+    };
+
+    if out.is_null() { return Err(EfiError(EfiStatus::UNSUPPORTED)); }
+
+    Ok(unsafe { &*out })
+}
+
+/// Get the dimensions of the text mode `number`
+pub fn query_mode(number: usize) -> EfiResult<TextMode> {
+    let out = console_out()?;
+
+    let mut mode = TextMode { number, columns: 0, rows: 0 };
+
+    unsafe {
+        (out.query_mode)(out, number, &mut mode.columns, &mut mode.rows)
+    }.into_result()?;
+
+    Ok(mode)
+}
+
+/// Get the text mode currently in use
+pub fn current_mode() -> EfiResult<TextMode> {
+    let out = console_out()?;
+    query_mode(unsafe { (*out.mode).mode } as usize)
+}
+
+/// Get an iterator over all text modes supported by the console
+///
+/// Modes which the firmware reports as unsupported are skipped
+pub fn text_modes() -> impl Iterator<Item = TextMode> {
+    let max_mode = console_out()
+        .map(|out| unsafe { (*out.mode).max_mode } as usize)
+        .unwrap_or(0);
+
+    (0..max_mode).filter_map(|number| query_mode(number).ok())
+}
+
+/// Switch the console to the text mode `number`. This clears the screen.
+pub fn set_mode(number: usize) -> EfiResult<()> {
+    let out = console_out()?;
+    unsafe { (out.set_mode)(out, number) }.into_result()
+}
+
+/// Switch the console to the text mode with the most characters on screen
+///
+/// Returns the selected mode
+pub fn set_largest_mode() -> EfiResult<TextMode> {
+    let mode = text_modes()
+        .max_by_key(|mode| mode.columns * mode.rows)
+        .ok_or(EfiError(EfiStatus::UNSUPPORTED))?;
+
+    set_mode(mode.number)?;
+
+    Ok(mode)
+}
+
+/// Get the current foreground and background colors of `console`
+///
+/// `Console::Both` returns the colors of the console output device
+pub fn colors(console: Console) -> EfiResult<(Color, Color)> {
+    let attribute = unsafe { (*text_output(console)?.mode).attribute } as u8;
+    Ok((Color::from(attribute), Color::from(attribute >> 4)))
+}
+
+/// Set the colors used for the following output to `console` and
+/// `clear_screen()`
+///
+/// Fails with `EfiStatus::INVALID_PARAMETER` if `background` is not one of
+/// the first 8 colors
+pub fn set_colors(console: Console, foreground: Color, background: Color)
+        -> EfiResult<()> {
+    if background as u8 > Color::LightGray as u8 {
+        return Err(EfiError(EfiStatus::INVALID_PARAMETER));
+    }
+
+    // Setting the colors twice doesn't hurt when both are the same device
+    if console == Console::Both {
+        set_colors(Console::Out, foreground, background)?;
+        return set_colors(Console::Err, foreground, background);
+    }
+
+    let out = text_output(console)?;
+    let attribute = (foreground as usize) | ((background as usize) << 4);
+
+    unsafe { (out.set_attribute)(out, attribute) }.into_result()
+}
+
+/// Run `func` with the foreground color of `console` set to `color`,
+/// restoring the previous colors afterwards
+///
+/// `func` is still run if the colors can't be changed
+pub fn with_color<F: FnOnce()>(console: Console, color: Color, func: F) {
+    // Each device keeps its own colors to restore
+    if console == Console::Both {
+        with_color(Console::Out, color,
+            || with_color(Console::Err, color, func));
+        return;
+    }
+
+    let prev = colors(console).ok();
+
+    if let Some((_, background)) = prev {
+        let _ = set_colors(console, color, background);
+    }
+
+    func();
+
+    if let Some((foreground, background)) = prev {
+        let _ = set_colors(console, foreground, background);
+    }
+}
+
+/// Clear the screen to the current background color and move the cursor to
+/// the top left corner
+pub fn clear_screen() -> EfiResult<()> {
+    let out = console_out()?;
+    unsafe { (out.clear_screen)(out) }.into_result()
+}
+
+/// Move the cursor to `column` and `row`, both starting at 0
+pub fn set_cursor_position(column: usize, row: usize) -> EfiResult<()> {
+    let out = console_out()?;
+    unsafe { (out.set_cursor_position)(out, column, row) }.into_result()
+}
+
+/// Get the current `(column, row)` of the cursor
+pub fn cursor_position() -> EfiResult<(usize, usize)> {
+    let mode = unsafe { &*console_out()?.mode };
+    Ok((mode.cursor_column as usize, mode.cursor_row as usize))
+}
+
+/// Show or hide the cursor
+pub fn enable_cursor(visible: bool) -> EfiResult<()> {
+    let out = console_out()?;
+    unsafe { (out.enable_cursor)(out, visible) }.into_result()
+}
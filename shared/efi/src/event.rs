@@ -0,0 +1,238 @@
+//! Events and timers: waiting, timeouts and notification callbacks
+
+use alloc::boxed::Box;
+use core::time::Duration;
+use crate::{boot_services, EfiEvent, EfiResult, EfiStatus};
+
+/// Event type: the event is a timer which can be armed with `SetTimer()`
+pub const EVT_TIMER: u32 = 0x80000000;
+
+/// Event type: the event is allocated from runtime memory
+pub const EVT_RUNTIME: u32 = 0x40000000;
+
+/// Event type: the notification function is called when the event is waited
+/// on or checked
+pub const EVT_NOTIFY_WAIT: u32 = 0x00000100;
+
+/// Event type: the notification function is called when the event is
+/// signaled
+pub const EVT_NOTIFY_SIGNAL: u32 = 0x00000200;
+
+/// Event type: the event is signaled when `ExitBootServices()` is called
+pub const EVT_SIGNAL_EXIT_BOOT_SERVICES: u32 = 0x00000201;
+
+/// Event type: the event is signaled when `SetVirtualAddressMap()` is called
+pub const EVT_SIGNAL_VIRTUAL_ADDRESS_CHANGE: u32 = 0x60000202;
+
+/// Task priority level of normal application code
+pub const TPL_APPLICATION: EfiTpl = 4;
+
+/// Task priority level of most notification functions
+pub const TPL_CALLBACK: EfiTpl = 8;
+
+/// Task priority level of notification functions which must not block
+pub const TPL_NOTIFY: EfiTpl = 16;
+
+/// Task priority level with interrupts disabled
+pub const TPL_HIGH_LEVEL: EfiTpl = 31;
+
+/// A task priority level
+pub type EfiTpl = usize;
+
+/// Notification function of an event, called with the event and the context
+/// given to `CreateEvent()`
+pub type EfiEventNotify = unsafe extern "efiapi" fn(
+    event:   EfiEvent,
+    context: *mut u8,
+);
+
+/// Kind of timer armed by `SetTimer()`
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub enum EfiTimerDelay {
+    /// Cancel the timer
+    TimerCancel,
+
+    /// Signal the event every time the trigger time elapses
+    TimerPeriodic,
+
+    /// Signal the event once after the trigger time
+    TimerRelative,
+}
+
+/// When a timer event is signaled
+#[derive(Clone, Copy, Debug)]
+pub enum Timer {
+    /// Never, cancels a previously armed timer
+    Cancel,
+
+    /// Every time the duration elapses
+    Periodic(Duration),
+
+    /// Once, after the duration elapses
+    Relative(Duration),
+}
+
+/// A notification callback, boxed twice so it fits in the context pointer
+type Callback = Box<dyn FnMut() + 'static>;
+
+/// Convert `duration` into the 100 nanosecond units of `SetTimer()`
+fn timer_ticks(duration: Duration) -> u64 {
+    (duration.as_nanos() / 100).min(u64::MAX as u128) as u64
+}
+
+/// Notification function calling the `Callback` in `context`
+unsafe extern "efiapi" fn notify_trampoline(_event: EfiEvent,
+        context: *mut u8) {
+    let callback = &mut *(context as *mut Callback);
+    callback();
+}
+
+/// An event created with `CreateEvent()`, closed when dropped
+pub struct Event {
+    /// The firmware event
+    event: EfiEvent,
+
+    /// The notification callback, owned by the event
+    callback: *mut Callback,
+}
+
+impl Event {
+    /// Create an event of `typ`, calling `callback` at `TPL_CALLBACK` when it
+    /// is signaled
+    fn create(typ: u32, callback: Option<Callback>) -> EfiResult<Event> {
+        let bs = boot_services()?;
+
+        let callback = callback
+            .map_or(core::ptr::null_mut(), |cb| Box::into_raw(Box::new(cb)));
+
+        let (notify_tpl, notify_function) = if callback.is_null() {
+            (TPL_APPLICATION, None)
+        } else {
+            (TPL_CALLBACK, Some(notify_trampoline as EfiEventNotify))
+        };
+
+        let mut event = EfiEvent::NULL;
+
+        let ret = unsafe {
+            (bs.create_event)(typ, notify_tpl, notify_function,
+                callback as *mut u8, &mut event)
+        };
+
+        if let Err(err) = ret.into_result() {
+            // The firmware never got to see the callback
+            if !callback.is_null() {
+                drop(unsafe { Box::from_raw(callback) });
+            }
+
+            return Err(err);
+        }
+
+        Ok(Event { event, callback })
+    }
+
+    /// Create a timer event to wait on, armed with `set_timer()`
+    pub fn timer() -> EfiResult<Event> {
+        Event::create(EVT_TIMER, None)
+    }
+
+    /// Create a timer event calling `callback` every time it is signaled
+    ///
+    /// The callback runs at `TPL_CALLBACK`, interrupting the code running at
+    /// the time, so it must only touch state which is safe to access from
+    /// there. Such events can't be waited on.
+    pub fn timer_with_callback<F>(callback: F) -> EfiResult<Event>
+            where F: FnMut() + 'static {
+        Event::create(EVT_TIMER | EVT_NOTIFY_SIGNAL, Some(Box::new(callback)))
+    }
+
+    /// Create an event calling `callback` when signaled with `signal()`
+    ///
+    /// The same restrictions as for `timer_with_callback()` apply
+    pub fn with_callback<F>(callback: F) -> EfiResult<Event>
+            where F: FnMut() + 'static {
+        Event::create(EVT_NOTIFY_SIGNAL, Some(Box::new(callback)))
+    }
+
+    /// Get the firmware event, for example to pass to `wait_for_event()`
+    pub fn raw(&self) -> EfiEvent {
+        self.event
+    }
+
+    /// Arm or cancel the timer of this event
+    pub fn set_timer(&self, timer: Timer) -> EfiResult<()> {
+        let (typ, ticks) = match timer {
+            Timer::Cancel => (EfiTimerDelay::TimerCancel, 0),
+            Timer::Periodic(period) =>
+                (EfiTimerDelay::TimerPeriodic, timer_ticks(period)),
+            Timer::Relative(delay) =>
+                (EfiTimerDelay::TimerRelative, timer_ticks(delay)),
+        };
+
+        unsafe {
+            (boot_services()?.set_timer)(self.event, typ, ticks)
+        }.into_result()
+    }
+
+    /// Signal this event, calling its callback if it has one
+    pub fn signal(&self) -> EfiResult<()> {
+        unsafe { (boot_services()?.signal_event)(self.event) }.into_result()
+    }
+
+    /// Check whether this event is signaled, clearing the signaled state
+    ///
+    /// Fails with `EfiStatus::INVALID_PARAMETER` for events with a callback
+    pub fn is_signaled(&self) -> EfiResult<bool> {
+        let ret = unsafe { (boot_services()?.check_event)(self.event) };
+
+        if ret == EfiStatus::NOT_READY { return Ok(false); }
+
+        ret.into_result()?;
+        Ok(true)
+    }
+
+    /// Wait until this event is signaled
+    pub fn wait(&self) -> EfiResult<()> {
+        wait_for_event(&[self.event]).map(|_| ())
+    }
+}
+
+impl Drop for Event {
+    fn drop(&mut self) {
+        let closed = match boot_services() {
+            Ok(bs) => {
+                let ret = unsafe { (bs.close_event)(self.event) };
+                ret == EfiStatus::SUCCESS
+            }
+            Err(_) => false,
+        };
+
+        // Once the event is closed the callback can't run anymore. If it
+        // couldn't be closed the firmware may still call it, leak it then.
+        if closed && !self.callback.is_null() {
+            drop(unsafe { Box::from_raw(self.callback) });
+        }
+    }
+}
+
+/// Wait until one of `events` is signaled
+///
+/// Returns the index of the signaled event in `events`. This must be called
+/// at `TPL_APPLICATION`, thus not from a notification callback.
+pub fn wait_for_event(events: &[EfiEvent]) -> EfiResult<usize> {
+    let mut index = 0;
+
+    unsafe {
+        (boot_services()?.wait_for_event)(events.len(), events.as_ptr(),
+            &mut index)
+    }.into_result()?;
+
+    Ok(index)
+}
+
+/// Wait for `duration` to elapse, letting the firmware idle meanwhile
+pub fn sleep(duration: Duration) -> EfiResult<()> {
+    let timer = Event::timer()?;
+    timer.set_timer(Timer::Relative(duration))?;
+    timer.wait()
+}
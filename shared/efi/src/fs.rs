@@ -0,0 +1,409 @@
+//! Simple File System Protocol: reading files from FAT volumes such as the
+//! EFI system partition
+
+use alloc::vec::Vec;
+use crate::{loaded_image, locate_handles, open, CStr16, CString16, EfiError};
+use crate::{EfiGuid, EfiHandle, EfiResult, EfiStatus, EfiTime, Protocol};
+use crate::{boot_services, Ucs2Error};
+
+/// GUID of the Simple File System Protocol
+pub const SIMPLE_FILE_SYSTEM_PROTOCOL_GUID: EfiGuid = EfiGuid::new(
+    0x964e5b22, 0x6459, 0x11d2,
+    [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b]);
+
+/// GUID of the `EFI_FILE_INFO` structure returned by `GetInfo()`
+pub const FILE_INFO_GUID: EfiGuid = EfiGuid::new(
+    0x09576e92, 0x6d3f, 0x11d2,
+    [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b]);
+
+/// Open mode: open the file for reading
+pub const FILE_MODE_READ: u64 = 0x0000000000000001;
+
+/// Open mode: open the file for writing
+pub const FILE_MODE_WRITE: u64 = 0x0000000000000002;
+
+/// Open mode: create the file if it doesn't exist
+pub const FILE_MODE_CREATE: u64 = 0x8000000000000000;
+
+/// File attribute: the file is read only
+pub const FILE_READ_ONLY: u64 = 0x01;
+
+/// File attribute: the file is hidden
+pub const FILE_HIDDEN: u64 = 0x02;
+
+/// File attribute: the file is a system file
+pub const FILE_SYSTEM: u64 = 0x04;
+
+/// File attribute: the file is a directory
+pub const FILE_DIRECTORY: u64 = 0x10;
+
+/// File attribute: the file has been modified since the last backup
+pub const FILE_ARCHIVE: u64 = 0x20;
+
+/// Provides a minimal interface for file-type access to a device.
+#[repr(C)]
+pub struct EfiSimpleFileSystemProtocol {
+    /// The version of the EFI_SIMPLE_FILE_SYSTEM_PROTOCOL.
+    pub revision: u64,
+
+    /// Opens the volume for file I/O access.
+    pub open_volume: unsafe extern "efiapi" fn(
+        this: *const EfiSimpleFileSystemProtocol,
+        root: &mut *mut EfiFileProtocol,
+    ) -> EfiStatus,
+}
+
+/// Short name of the Simple File System Protocol
+pub type SimpleFileSystem = EfiSimpleFileSystemProtocol;
+
+unsafe impl Protocol for EfiSimpleFileSystemProtocol {
+    const GUID: EfiGuid = SIMPLE_FILE_SYSTEM_PROTOCOL_GUID;
+}
+
+/// Provides file based access to supported file systems.
+#[repr(C)]
+pub struct EfiFileProtocol {
+    /// The version of the EFI_FILE_PROTOCOL interface.
+    pub revision: u64,
+
+    /// Opens a new file relative to the source file's location.
+    pub open: unsafe extern "efiapi" fn(
+        this:       *mut EfiFileProtocol,
+        new_handle: &mut *mut EfiFileProtocol,
+        file_name:  *const u16,
+        open_mode:  u64,
+        attributes: u64,
+    ) -> EfiStatus,
+
+    /// Closes a specified file handle.
+    pub close: unsafe extern "efiapi" fn(
+        this: *mut EfiFileProtocol,
+    ) -> EfiStatus,
+
+    /// Closes and deletes a file.
+    pub delete: unsafe extern "efiapi" fn(
+        this: *mut EfiFileProtocol,
+    ) -> EfiStatus,
+
+    /// Reads data from a file.
+    pub read: unsafe extern "efiapi" fn(
+        this:        *mut EfiFileProtocol,
+        buffer_size: &mut usize,
+        buffer:      *mut u8,
+    ) -> EfiStatus,
+
+    /// Writes data to a file.
+    pub write: unsafe extern "efiapi" fn(
+        this:        *mut EfiFileProtocol,
+        buffer_size: &mut usize,
+        buffer:      *const u8,
+    ) -> EfiStatus,
+
+    /// Returns a file's current position.
+    pub get_position: unsafe extern "efiapi" fn(
+        this:     *mut EfiFileProtocol,
+        position: &mut u64,
+    ) -> EfiStatus,
+
+    /// Sets a file's current position.
+    pub set_position: unsafe extern "efiapi" fn(
+        this:     *mut EfiFileProtocol,
+        position: u64,
+    ) -> EfiStatus,
+
+    /// Returns information about a file.
+    pub get_info: unsafe extern "efiapi" fn(
+        this:             *mut EfiFileProtocol,
+        information_type: *const EfiGuid,
+        buffer_size:      &mut usize,
+        buffer:           *mut u8,
+    ) -> EfiStatus,
+
+    /// Sets information about a file.
+    pub set_info: unsafe extern "efiapi" fn(
+        this:             *mut EfiFileProtocol,
+        information_type: *const EfiGuid,
+        buffer_size:      usize,
+        buffer:           *const u8,
+    ) -> EfiStatus,
+
+    /// Flushes all modified data associated with a file to a device.
+    pub flush: unsafe extern "efiapi" fn(
+        this: *mut EfiFileProtocol,
+    ) -> EfiStatus,
+}
+
+/// Fixed size header of `EFI_FILE_INFO`, followed by the null terminated
+/// UCS-2 file name
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct EfiFileInfoHeader {
+    size:              u64,
+    file_size:         u64,
+    physical_size:     u64,
+    create_time:       EfiTime,
+    last_access_time:  EfiTime,
+    modification_time: EfiTime,
+    attribute:         u64,
+}
+
+/// Information about a file or directory
+#[derive(Clone, Debug)]
+pub struct FileInfo {
+    /// Name of the file, without its path
+    pub name: CString16,
+
+    /// Size of the file in bytes
+    pub size: u64,
+
+    /// Amount of space the file uses on the volume in bytes
+    pub physical_size: u64,
+
+    /// Time the file was created
+    pub create_time: EfiTime,
+
+    /// Time the file was last accessed
+    pub last_access_time: EfiTime,
+
+    /// Time the file was last modified
+    pub modification_time: EfiTime,
+
+    /// `FILE_*` attribute bits
+    pub attribute: u64,
+}
+
+impl FileInfo {
+    /// Parse an `EFI_FILE_INFO` structure from `buffer`
+    fn parse(buffer: &[u8]) -> EfiResult<Self> {
+        let header_size = core::mem::size_of::<EfiFileInfoHeader>();
+
+        if buffer.len() < header_size {
+            return Err(EfiError(EfiStatus::VOLUME_CORRUPTED));
+        }
+
+        let header = unsafe {
+            core::ptr::read_unaligned(buffer.as_ptr()
+                as *const EfiFileInfoHeader)
+        };
+
+        // The name runs up to the null terminator or the end of the buffer
+        let mut name: Vec<u16> = buffer[header_size..].chunks_exact(2)
+            .map(|chr| u16::from_le_bytes([chr[0], chr[1]]))
+            .take_while(|&chr| chr != 0)
+            .collect();
+        name.push(0);
+
+        Ok(FileInfo {
+            name: CString16::from_vec_with_nul(name)?,
+            size:              header.file_size,
+            physical_size:     header.physical_size,
+            create_time:       header.create_time,
+            last_access_time:  header.last_access_time,
+            modification_time: header.modification_time,
+            attribute:         header.attribute,
+        })
+    }
+
+    /// Returns `true` if this is a directory
+    pub fn is_directory(&self) -> bool {
+        (self.attribute & FILE_DIRECTORY) != 0
+    }
+}
+
+/// Convert `path` into a file path for `File::open()`, accepting `/` as well
+/// as `\` as the separator
+pub fn file_path(path: &str) -> Result<CString16, Ucs2Error> {
+    let mut encoded = CString16::new();
+
+    for chr in path.chars() {
+        encoded.push(if chr == '/' { '\\' } else { chr })?;
+    }
+
+    Ok(encoded)
+}
+
+/// An open file or directory, closed when dropped
+pub struct File {
+    /// The file protocol instance of this file
+    handle: *mut EfiFileProtocol,
+}
+
+impl File {
+    /// Open the root directory of the volume with the Simple File System
+    /// Protocol on `device`
+    pub fn open_volume(device: EfiHandle) -> EfiResult<File> {
+        let fs = open::<SimpleFileSystem>(device)?;
+
+        let mut root = core::ptr::null_mut();
+        unsafe { (fs.open_volume)(fs, &mut root) }.into_result()?;
+
+        Ok(File { handle: root })
+    }
+
+    /// Open `path` relative to this directory for reading
+    ///
+    /// The firmware only accepts `\` as the separator, see `file_path()` to
+    /// convert paths using `/`.
+    pub fn open(&self, path: &CStr16) -> EfiResult<File> {
+        let mut handle = core::ptr::null_mut();
+
+        unsafe {
+            ((*self.handle).open)(self.handle, &mut handle, path.as_ptr(),
+                FILE_MODE_READ, 0)
+        }.into_result()?;
+
+        Ok(File { handle })
+    }
+
+    /// Read from the current position into `buffer`
+    ///
+    /// Returns the number of bytes read, 0 at the end of the file. For
+    /// directories, use `read_dir()` instead.
+    pub fn read(&mut self, buffer: &mut [u8]) -> EfiResult<usize> {
+        let mut size = buffer.len();
+
+        unsafe {
+            ((*self.handle).read)(self.handle, &mut size, buffer.as_mut_ptr())
+        }.into_result()?;
+
+        Ok(size)
+    }
+
+    /// Read everything from the current position to the end of the file
+    pub fn read_to_end(&mut self) -> EfiResult<Vec<u8>> {
+        let remaining = self.info()?.size.saturating_sub(self.position()?);
+
+        // The size comes from the file system, don't let a bogus one abort
+        let mut data = Vec::new();
+        data.try_reserve_exact(remaining as usize)
+            .map_err(|_| EfiError(EfiStatus::OUT_OF_RESOURCES))?;
+        data.resize(remaining as usize, 0);
+
+        let mut filled = 0;
+
+        while filled < data.len() {
+            let bytes = self.read(&mut data[filled..])?;
+            if bytes == 0 { break; }
+            filled += bytes;
+        }
+
+        data.truncate(filled);
+        Ok(data)
+    }
+
+    /// Get the current position in the file
+    pub fn position(&mut self) -> EfiResult<u64> {
+        let mut position = 0;
+
+        unsafe {
+            ((*self.handle).get_position)(self.handle, &mut position)
+        }.into_result()?;
+
+        Ok(position)
+    }
+
+    /// Move to `position` in the file. `u64::MAX` moves to the end of the
+    /// file. Directories can only be rewound to 0.
+    pub fn set_position(&mut self, position: u64) -> EfiResult<()> {
+        unsafe {
+            ((*self.handle).set_position)(self.handle, position)
+        }.into_result()
+    }
+
+    /// Get information about this file
+    pub fn info(&mut self) -> EfiResult<FileInfo> {
+        // Ask for the size of the information first
+        let mut size = 0;
+
+        let ret = unsafe {
+            ((*self.handle).get_info)(self.handle, &FILE_INFO_GUID, &mut size,
+                core::ptr::null_mut())
+        };
+
+        if ret != EfiStatus::BUFFER_TOO_SMALL {
+            ret.into_result()?;
+        }
+
+        let mut buffer = alloc::vec![0u8; size];
+
+        unsafe {
+            ((*self.handle).get_info)(self.handle, &FILE_INFO_GUID, &mut size,
+                buffer.as_mut_ptr())
+        }.into_result()?;
+
+        FileInfo::parse(&buffer[..size])
+    }
+
+    /// Read the next entry of this directory
+    ///
+    /// Returns `None` once all entries have been read
+    pub fn read_dir(&mut self) -> EfiResult<Option<FileInfo>> {
+        // Ask for the size of the next entry first, an empty read means
+        // there are no more entries
+        let mut size = 0;
+
+        let ret = unsafe {
+            ((*self.handle).read)(self.handle, &mut size,
+                core::ptr::null_mut())
+        };
+
+        if ret != EfiStatus::BUFFER_TOO_SMALL {
+            ret.into_result()?;
+            return Ok(None);
+        }
+
+        let mut buffer = alloc::vec![0u8; size];
+        let size = self.read(&mut buffer)?;
+
+        if size == 0 { return Ok(None); }
+
+        FileInfo::parse(&buffer[..size]).map(Some)
+    }
+
+    /// Get all entries of this directory, including `.` and `..`
+    pub fn entries(&mut self) -> EfiResult<Vec<FileInfo>> {
+        self.set_position(0)?;
+
+        let mut entries = Vec::new();
+
+        while let Some(entry) = self.read_dir()? {
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        // The file system driver is gone along with boot services
+        if boot_services().is_err() { return; }
+
+        unsafe { ((*self.handle).close)(self.handle); }
+    }
+}
+
+/// Open the root directory of the volume the running image was loaded from
+pub fn boot_volume() -> EfiResult<File> {
+    File::open_volume(loaded_image()?.device())
+}
+
+/// Get the handles of all volumes with a file system
+pub fn volumes() -> EfiResult<Vec<EfiHandle>> {
+    locate_handles::<SimpleFileSystem>()
+}
+
+/// Read the whole file at `path` on the boot volume
+pub fn read_file(path: &str) -> EfiResult<Vec<u8>> {
+    boot_volume()?.open(&file_path(path)?)?.read_to_end()
+}
+
+/// Get information about the file at `path` on the boot volume
+pub fn metadata(path: &str) -> EfiResult<FileInfo> {
+    boot_volume()?.open(&file_path(path)?)?.info()
+}
+
+/// List the directory at `path` on the boot volume
+pub fn read_dir(path: &str) -> EfiResult<Vec<FileInfo>> {
+    boot_volume()?.open(&file_path(path)?)?.entries()
+}
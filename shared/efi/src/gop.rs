@@ -0,0 +1,266 @@
+//! Graphics Output Protocol: mode selection and framebuffer acquisition
+
+use crate::{free_pool, locate, EfiError, EfiGuid, EfiResult, EfiStatus};
+use crate::Protocol;
+
+/// GUID of the Graphics Output Protocol
+pub const GRAPHICS_OUTPUT_PROTOCOL_GUID: EfiGuid = EfiGuid::new(
+    0x9042a9de, 0x23dc, 0x4a38,
+    [0x96, 0xfb, 0x7a, 0xde, 0xd0, 0x80, 0x51, 0x6a]);
+
+/// Bit masks of the color channels of a pixel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct EfiPixelBitmask {
+    pub red_mask:      u32,
+    pub green_mask:    u32,
+    pub blue_mask:     u32,
+    pub reserved_mask: u32,
+}
+
+/// Description of a graphics mode as returned by `QueryMode()`
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct EfiGraphicsOutputModeInformation {
+    /// The version of this data structure.
+    pub version: u32,
+
+    /// The size of video screen in pixels in the X dimension.
+    pub horizontal_resolution: u32,
+
+    /// The size of video screen in pixels in the Y dimension.
+    pub vertical_resolution: u32,
+
+    /// The physical format of the pixel. This is an EFI_GRAPHICS_PIXEL_FORMAT
+    /// value, kept raw as firmware may return values we don't know about.
+    pub pixel_format: u32,
+
+    /// The bit mask of each color channel, only valid when `pixel_format` is
+    /// PixelBitMask.
+    pub pixel_information: EfiPixelBitmask,
+
+    /// Number of pixels per video memory line.
+    pub pixels_per_scan_line: u32,
+}
+
+/// The mode the graphics device is currently in
+#[derive(Debug)]
+#[repr(C)]
+pub struct EfiGraphicsOutputProtocolMode {
+    /// The number of modes supported by QueryMode() and SetMode().
+    pub max_mode: u32,
+
+    /// Current mode of the graphics device.
+    pub mode: u32,
+
+    /// Pointer to read-only EFI_GRAPHICS_OUTPUT_MODE_INFORMATION data.
+    pub info: *const EfiGraphicsOutputModeInformation,
+
+    /// Size of `info` structure in bytes.
+    pub size_of_info: usize,
+
+    /// Base address of graphics linear frame buffer.
+    pub frame_buffer_base: u64,
+
+    /// Amount of frame buffer needed to support the active mode.
+    pub frame_buffer_size: usize,
+}
+
+/// Provides a basic abstraction to set video modes and copy pixels to and
+/// from the graphics controller's frame buffer.
+#[repr(C)]
+pub struct EfiGraphicsOutputProtocol {
+    /// Returns information for an available graphics mode that the graphics
+    /// device and the set of active video output devices supports.
+    pub query_mode: unsafe extern "efiapi" fn(
+        this:         *const EfiGraphicsOutputProtocol,
+        mode_number:  u32,
+        size_of_info: &mut usize,
+        info:         &mut *mut EfiGraphicsOutputModeInformation,
+    ) -> EfiStatus,
+
+    /// Set the video device into the specified mode and clears the visible
+    /// portions of the output display to black.
+    pub set_mode: unsafe extern "efiapi" fn(
+        this:        *const EfiGraphicsOutputProtocol,
+        mode_number: u32,
+    ) -> EfiStatus,
+
+    /// Software abstraction to draw on the video device's frame buffer.
+    pub _blt: usize,
+
+    /// Pointer to EFI_GRAPHICS_OUTPUT_PROTOCOL_MODE data.
+    pub mode: *const EfiGraphicsOutputProtocolMode,
+}
+
+/// Layout of a pixel in the framebuffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 32-bit pixels, byte 0 is red, byte 1 green, byte 2 blue
+    Rgb,
+
+    /// 32-bit pixels, byte 0 is blue, byte 1 green, byte 2 red
+    Bgr,
+
+    /// Pixels described by the channel masks
+    Bitmask(EfiPixelBitmask),
+
+    /// No framebuffer is available, only `Blt()` can be used
+    BltOnly,
+}
+
+impl PixelFormat {
+    /// Parse the pixel format of a mode description
+    fn from_info(info: &EfiGraphicsOutputModeInformation) -> Option<Self> {
+        match info.pixel_format {
+            0 => Some(PixelFormat::Rgb),
+            1 => Some(PixelFormat::Bgr),
+            2 => Some(PixelFormat::Bitmask(info.pixel_information)),
+            3 => Some(PixelFormat::BltOnly),
+            _ => None,
+        }
+    }
+}
+
+/// A graphics mode supported by the display
+#[derive(Clone, Copy, Debug)]
+pub struct GraphicsMode {
+    /// The mode number to pass to `set_graphics_mode()`
+    pub number: u32,
+
+    /// Horizontal resolution in pixels
+    pub width: u32,
+
+    /// Vertical resolution in pixels
+    pub height: u32,
+
+    /// Number of pixels per line in memory, may be larger than `width`
+    pub stride: u32,
+
+    /// Layout of a pixel
+    pub format: PixelFormat,
+}
+
+impl GraphicsMode {
+    /// Build a mode description from the firmware's information
+    fn from_info(number: u32, info: &EfiGraphicsOutputModeInformation)
+            -> Option<Self> {
+        Some(GraphicsMode {
+            number,
+            width:  info.horizontal_resolution,
+            height: info.vertical_resolution,
+            stride: info.pixels_per_scan_line,
+            format: PixelFormat::from_info(info)?,
+        })
+    }
+}
+
+/// A linear framebuffer
+///
+/// This is a plain description of memory, thus it stays valid after boot
+/// services have been exited.
+#[derive(Clone, Copy, Debug)]
+pub struct Framebuffer {
+    /// Physical address of the first pixel
+    pub base: u64,
+
+    /// Size of the framebuffer in bytes
+    pub size: usize,
+
+    /// Horizontal resolution in pixels
+    pub width: u32,
+
+    /// Vertical resolution in pixels
+    pub height: u32,
+
+    /// Number of pixels per line in memory, may be larger than `width`
+    pub stride: u32,
+
+    /// Layout of a pixel
+    pub format: PixelFormat,
+}
+
+/// Short name of the Graphics Output Protocol
+pub type Gop = EfiGraphicsOutputProtocol;
+
+unsafe impl Protocol for EfiGraphicsOutputProtocol {
+    const GUID: EfiGuid = GRAPHICS_OUTPUT_PROTOCOL_GUID;
+}
+
+/// Get the Graphics Output Protocol of the first display
+pub fn graphics_output() -> EfiResult<&'static EfiGraphicsOutputProtocol> {
+    locate::<Gop>()
+}
+
+/// Get the description of the graphics mode `number`
+pub fn query_graphics_mode(number: u32) -> EfiResult<GraphicsMode> {
+    let gop = graphics_output()?;
+
+    let mut size = 0;
+    let mut info = core::ptr::null_mut();
+
+    unsafe {
+        (gop.query_mode)(gop, number, &mut size, &mut info)
+    }.into_result()?;
+
+    // The firmware allocated the information from the pool, copy it out
+    // before giving it back
+    let mode = GraphicsMode::from_info(number, unsafe { &*info });
+    let _ = unsafe { free_pool(info as *mut u8) };
+
+    mode.ok_or(EfiError(EfiStatus::UNSUPPORTED))
+}
+
+/// Get an iterator over all graphics modes supported by the display
+pub fn graphics_modes() -> EfiResult<impl Iterator<Item = GraphicsMode>> {
+    let max_mode = unsafe { (*graphics_output()?.mode).max_mode };
+
+    Ok((0..max_mode).filter_map(|number| query_graphics_mode(number).ok()))
+}
+
+/// Get the framebuffer of the current graphics mode
+///
+/// Fails with `EfiStatus::UNSUPPORTED` if the mode has no linear framebuffer
+pub fn framebuffer() -> EfiResult<Framebuffer> {
+    let mode = unsafe { &*graphics_output()?.mode };
+    let info = unsafe { &*mode.info };
+
+    let format = PixelFormat::from_info(info)
+        .ok_or(EfiError(EfiStatus::UNSUPPORTED))?;
+
+    if format == PixelFormat::BltOnly {
+        return Err(EfiError(EfiStatus::UNSUPPORTED));
+    }
+
+    Ok(Framebuffer {
+        base:   mode.frame_buffer_base,
+        size:   mode.frame_buffer_size,
+        width:  info.horizontal_resolution,
+        height: info.vertical_resolution,
+        stride: info.pixels_per_scan_line,
+        format,
+    })
+}
+
+/// Switch the display to the graphics mode `number`
+pub fn set_graphics_mode(number: u32) -> EfiResult<Framebuffer> {
+    let gop = graphics_output()?;
+
+    unsafe { (gop.set_mode)(gop, number) }.into_result()?;
+
+    framebuffer()
+}
+
+/// Switch the display to a mode with a framebuffer of `width` by `height`
+/// pixels
+///
+/// Fails with `EfiStatus::NOT_FOUND` if no such mode exists
+pub fn set_graphics_resolution(width: u32, height: u32)
+        -> EfiResult<Framebuffer> {
+    let mode = graphics_modes()?
+        .find(|mode| mode.width == width && mode.height == height &&
+            mode.format != PixelFormat::BltOnly)
+        .ok_or(EfiError(EfiStatus::NOT_FOUND))?;
+
+    set_graphics_mode(mode.number)
+}
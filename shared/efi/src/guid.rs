@@ -0,0 +1,177 @@
+//! EFI GUIDs and a registry of the well-known ones
+
+use core::fmt;
+use core::str::FromStr;
+use crate::config_table::*;
+use crate::fs::*;
+use crate::gop::*;
+use crate::loaded_image::*;
+use crate::protocol::*;
+use crate::pxe::*;
+use crate::runtime::*;
+
+/// An Efi guid representation
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct EfiGuid {
+    pub data1: u32,
+    pub data2: u16,
+    pub data3: u16,
+    pub data4: [u8; 8],
+}
+
+impl EfiGuid {
+    /// Create a new GUID from its fields
+    pub const fn new(data1: u32, data2: u16, data3: u16, data4: [u8; 8])
+            -> Self {
+        EfiGuid { data1, data2, data3, data4 }
+    }
+
+    /// Get the registered name of this GUID, if it is a well-known one
+    pub fn name(&self) -> Option<&'static str> {
+        KNOWN_GUIDS.iter()
+            .find(|(guid, _)| guid == self)
+            .map(|&(_, name)| name)
+    }
+}
+
+impl fmt::Display for EfiGuid {
+    /// Display the GUID in the canonical
+    /// `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx` form
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-",
+            self.data1, self.data2, self.data3,
+            self.data4[0], self.data4[1])?;
+
+        for byte in &self.data4[2..] {
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for EfiGuid {
+    /// Display the registered name of the GUID, falling back to the
+    /// canonical form for unknown GUIDs
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None       => write!(f, "{}", self),
+        }
+    }
+}
+
+/// Errors which can occur while parsing a GUID from a string
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseGuidError {
+    /// The string is not 36 characters long
+    InvalidLength,
+
+    /// A dash is missing from the expected position
+    MissingDash(usize),
+
+    /// A character which is not a hex digit was found at the given offset
+    InvalidDigit(usize),
+}
+
+impl FromStr for EfiGuid {
+    type Err = ParseGuidError;
+
+    /// Parse a GUID in the canonical `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`
+    /// form. Hex digits can be either upper or lower case.
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let bytes = string.as_bytes();
+
+        if bytes.len() != 36 { return Err(ParseGuidError::InvalidLength); }
+
+        // Make sure the dashes are where we expect them
+        for &off in &[8, 13, 18, 23] {
+            if bytes[off] != b'-' {
+                return Err(ParseGuidError::MissingDash(off));
+            }
+        }
+
+        // Parse `len` hex digits starting at `off`
+        let hex = |off: usize, len: usize| -> Result<u64, ParseGuidError> {
+            let mut val = 0u64;
+
+            for (ii, &chr) in bytes[off..off + len].iter().enumerate() {
+                let digit = (chr as char).to_digit(16)
+                    .ok_or(ParseGuidError::InvalidDigit(off + ii))?;
+                val = (val << 4) | digit as u64;
+            }
+
+            Ok(val)
+        };
+
+        let mut data4 = [0u8; 8];
+        data4[0] = hex(19, 2)? as u8;
+        data4[1] = hex(21, 2)? as u8;
+        for (ii, byte) in data4[2..].iter_mut().enumerate() {
+            *byte = hex(24 + ii * 2, 2)? as u8;
+        }
+
+        Ok(EfiGuid::new(
+            hex(0, 8)? as u32,
+            hex(9, 4)? as u16,
+            hex(14, 4)? as u16,
+            data4,
+        ))
+    }
+}
+
+/// Registry of well-known GUIDs and their names
+static KNOWN_GUIDS: &[(EfiGuid, &str)] = &[
+    (ACPI_TABLE_GUID,         "ACPI_TABLE"),
+    (ACPI_20_TABLE_GUID,      "ACPI_20_TABLE"),
+    (SMBIOS_TABLE_GUID,       "SMBIOS_TABLE"),
+    (SMBIOS3_TABLE_GUID,      "SMBIOS3_TABLE"),
+    (DXE_SERVICES_TABLE_GUID, "DXE_SERVICES_TABLE"),
+    (DEVICE_TREE_TABLE_GUID,  "DEVICE_TREE_TABLE"),
+
+    (EFI_GLOBAL_VARIABLE_GUID, "EFI_GLOBAL_VARIABLE"),
+
+    (DEVICE_PATH_PROTOCOL_GUID,        "DEVICE_PATH_PROTOCOL"),
+    (SIMPLE_TEXT_INPUT_PROTOCOL_GUID,  "SIMPLE_TEXT_INPUT_PROTOCOL"),
+    (SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID, "SIMPLE_TEXT_OUTPUT_PROTOCOL"),
+    (GRAPHICS_OUTPUT_PROTOCOL_GUID,    "GRAPHICS_OUTPUT_PROTOCOL"),
+    (LOADED_IMAGE_PROTOCOL_GUID,       "LOADED_IMAGE_PROTOCOL"),
+    (SIMPLE_FILE_SYSTEM_PROTOCOL_GUID, "SIMPLE_FILE_SYSTEM_PROTOCOL"),
+    (FILE_INFO_GUID,                   "FILE_INFO"),
+    (PXE_BASE_CODE_PROTOCOL_GUID,      "PXE_BASE_CODE_PROTOCOL"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_round_trip() {
+        let text = "8be4df61-93ca-11d2-aa0d-00e098032b8c";
+        let guid: EfiGuid = text.parse().unwrap();
+
+        assert_eq!(guid, EFI_GLOBAL_VARIABLE_GUID);
+        assert_eq!(alloc::format!("{}", guid), text);
+    }
+
+    #[test]
+    fn parse_upper_case() {
+        let guid: EfiGuid = "8BE4DF61-93CA-11D2-AA0D-00E098032B8C".parse()
+            .unwrap();
+        assert_eq!(guid, EFI_GLOBAL_VARIABLE_GUID);
+    }
+
+    #[test]
+    fn parse_rejects_malformed() {
+        assert_eq!("".parse::<EfiGuid>(), Err(ParseGuidError::InvalidLength));
+        assert_eq!("8be4df61-93ca-11d2-aa0d-00e098032b8".parse::<EfiGuid>(),
+            Err(ParseGuidError::InvalidLength));
+        assert_eq!("8be4df61_93ca-11d2-aa0d-00e098032b8c".parse::<EfiGuid>(),
+            Err(ParseGuidError::MissingDash(8)));
+        assert_eq!("8be4df61-93ca-11d2-aa0d+00e098032b8c".parse::<EfiGuid>(),
+            Err(ParseGuidError::MissingDash(23)));
+        assert_eq!("8be4df61-93ca-11d2-aa0d-00e098032bxc".parse::<EfiGuid>(),
+            Err(ParseGuidError::InvalidDigit(34)));
+    }
+}
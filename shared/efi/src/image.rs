@@ -0,0 +1,227 @@
+//! Image services: loading, starting and unloading other EFI images
+
+use alloc::vec::Vec;
+use crate::{boot_services, free_pool, image_handle, open, open_raw};
+use crate::{EfiDevicePathProtocol, EfiError, EfiHandle, EfiResult, EfiStatus};
+use crate::{CStr16, CString16, LoadedImage};
+
+/// Device path node type of the node ending a device path
+const END_DEVICE_PATH_TYPE: u8 = 0x7f;
+
+/// Device path node sub-type ending the whole device path
+const END_ENTIRE_DEVICE_PATH_SUBTYPE: u8 = 0xff;
+
+/// Device path node type of media device paths
+const MEDIA_DEVICE_PATH_TYPE: u8 = 0x04;
+
+/// Media device path node sub-type of a file path
+const MEDIA_FILE_PATH_SUBTYPE: u8 = 0x04;
+
+/// Copy the nodes of the device path installed on `device`, without its end
+/// node
+fn device_path_nodes(device: EfiHandle) -> EfiResult<Vec<u8>> {
+    let mut node = open::<EfiDevicePathProtocol>(device)?
+        as *const EfiDevicePathProtocol as *const u8;
+    let mut nodes = Vec::new();
+
+    loop {
+        let header = unsafe { &*(node as *const EfiDevicePathProtocol) };
+        let length = u16::from_le_bytes(header.length) as usize;
+
+        if header.typ == END_DEVICE_PATH_TYPE &&
+                header.sub_type == END_ENTIRE_DEVICE_PATH_SUBTYPE {
+            break;
+        }
+
+        // A node shorter than its header would loop forever
+        if length < core::mem::size_of::<EfiDevicePathProtocol>() {
+            return Err(EfiError(EfiStatus::INVALID_PARAMETER));
+        }
+
+        nodes.extend_from_slice(unsafe {
+            core::slice::from_raw_parts(node, length)
+        });
+        node = unsafe { node.add(length) };
+    }
+
+    Ok(nodes)
+}
+
+/// Append a device path node of `typ` and `sub_type` with `data` to `path`
+fn push_device_path_node(path: &mut Vec<u8>, typ: u8, sub_type: u8,
+        data: &[u8]) -> EfiResult<()> {
+    let length = u16::try_from(data.len() + 4)
+        .map_err(|_| EfiError(EfiStatus::INVALID_PARAMETER))?;
+
+    path.extend_from_slice(&[typ, sub_type]);
+    path.extend_from_slice(&length.to_le_bytes());
+    path.extend_from_slice(data);
+
+    Ok(())
+}
+
+/// Build the device path of the file at `path` on the volume `device`
+fn file_device_path(device: EfiHandle, path: &CStr16)
+        -> EfiResult<Vec<u8>> {
+    let mut device_path = device_path_nodes(device)?;
+
+    let name: Vec<u8> = path.as_slice_with_nul().iter()
+        .flat_map(|chr| chr.to_le_bytes())
+        .collect();
+
+    push_device_path_node(&mut device_path, MEDIA_DEVICE_PATH_TYPE,
+        MEDIA_FILE_PATH_SUBTYPE, &name)?;
+    push_device_path_node(&mut device_path, END_DEVICE_PATH_TYPE,
+        END_ENTIRE_DEVICE_PATH_SUBTYPE, &[])?;
+
+    Ok(device_path)
+}
+
+/// An image loaded with `LoadImage()`, unloaded when dropped unless it was
+/// started
+pub struct Image {
+    /// The image handle of the loaded image
+    handle: EfiHandle,
+
+    /// Load options passed to the image
+    load_options: CString16,
+
+    /// Set once the image was started, the firmware unloads it when it exits
+    started: bool,
+}
+
+impl Image {
+    /// Load an image with `device_path` and optionally its contents
+    fn load(device_path: *const EfiDevicePathProtocol, data: Option<&[u8]>)
+            -> EfiResult<Image> {
+        let (buffer, size) = match data {
+            Some(data) => (data.as_ptr(), data.len()),
+            None       => (core::ptr::null(), 0),
+        };
+
+        let mut handle = EfiHandle::NULL;
+
+        unsafe {
+            (boot_services()?.load_image)(
+                false,
+                image_handle()?,
+                device_path,
+                buffer,
+                size,
+                &mut handle
+            )
+        }.into_result()?;
+
+        Ok(Image { handle, load_options: CString16::new(), started: false })
+    }
+
+    /// Load the image at `path` on the volume `device`
+    ///
+    /// The loaded image sees `device` as the device it was loaded from. See
+    /// `file_path()` to convert paths using `/` as the separator.
+    pub fn load_file(device: EfiHandle, path: &CStr16) -> EfiResult<Image> {
+        let device_path = file_device_path(device, path)?;
+
+        Image::load(device_path.as_ptr() as *const EfiDevicePathProtocol,
+            None)
+    }
+
+    /// Load an image from its contents in `data`, for example downloaded
+    /// over the network
+    ///
+    /// If `device` is given, the loaded image sees it as the device it was
+    /// loaded from, so it can find its own files there.
+    pub fn load_buffer(data: &[u8], device: Option<EfiHandle>)
+            -> EfiResult<Image> {
+        let device_path = match device {
+            Some(device) => {
+                let mut path = device_path_nodes(device)?;
+                push_device_path_node(&mut path, END_DEVICE_PATH_TYPE,
+                    END_ENTIRE_DEVICE_PATH_SUBTYPE, &[])?;
+                path
+            }
+            None => Vec::new(),
+        };
+
+        let device_path = if device_path.is_empty() {
+            core::ptr::null()
+        } else {
+            device_path.as_ptr() as *const EfiDevicePathProtocol
+        };
+
+        Image::load(device_path, Some(data))
+    }
+
+    /// Get the image handle of the loaded image
+    pub fn handle(&self) -> EfiHandle {
+        self.handle
+    }
+
+    /// Get the Loaded Image Protocol of the loaded image
+    pub fn loaded_image(&self) -> EfiResult<&'static LoadedImage> {
+        open::<LoadedImage>(self.handle)
+    }
+
+    /// Set the load options the image gets started with, such as its
+    /// command line
+    pub fn set_load_options(&mut self, options: &CStr16) -> EfiResult<()> {
+        let loaded_image = open_raw::<LoadedImage>(self.handle)?;
+
+        // The options are only read while the image runs, keep them alive
+        // with the image
+        self.load_options = options.to_cstring16();
+
+        unsafe {
+            (*loaded_image).load_options =
+                self.load_options.as_ptr() as *const u8;
+            (*loaded_image).load_options_size =
+                self.load_options.size_with_nul() as u32;
+        }
+
+        Ok(())
+    }
+
+    /// Start the image and wait for it to exit
+    ///
+    /// Returns the exit status of the image, an image failing to start and
+    /// an image exiting with an error both end up as errors.
+    pub fn start(mut self) -> EfiResult<()> {
+        let mut exit_data_size = 0;
+        let mut exit_data = core::ptr::null_mut();
+
+        self.started = true;
+
+        let ret = unsafe {
+            (boot_services()?.start_image)(self.handle, &mut exit_data_size,
+                &mut exit_data)
+        };
+
+        if !exit_data.is_null() {
+            let _ = unsafe { free_pool(exit_data as *mut u8) };
+        }
+
+        ret.into_result()
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        if self.started { return; }
+
+        if let Ok(bs) = boot_services() {
+            unsafe { (bs.unload_image)(self.handle); }
+        }
+    }
+}
+
+/// Exit the running image with `status`, returning to whoever started it
+///
+/// This only returns if boot services are not available or the image
+/// handle was not registered with `register_image_handle()`
+pub fn exit(status: EfiStatus) {
+    if let (Ok(bs), Ok(image)) = (boot_services(), image_handle()) {
+        unsafe {
+            (bs.exit)(image, status, 0, core::ptr::null());
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! Keyboard input from the UEFI console and a small line editor on top of it
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::time::Duration;
+use crate::{output_string, system_table, wait_for_event, EfiEvent, EfiInputKey};
+use crate::{EfiResult, EfiStatus, Event, Timer};
+
+/// A decoded key press
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    /// A printable character
+    Char(char),
+
+    Enter,
+    Backspace,
+    Tab,
+    Escape,
+    Up,
+    Down,
+    Right,
+    Left,
+    Home,
+    End,
+    Insert,
+    Delete,
+    PageUp,
+    PageDown,
+
+    /// Function key F1 to F24
+    Function(u8),
+
+    /// A key with a scan code we don't know about
+    Unknown(u16),
+}
+
+impl From<EfiInputKey> for Key {
+    fn from(key: EfiInputKey) -> Self {
+        use Key::*;
+
+        // Keys without a unicode representation are described by their scan
+        // code
+        if key.scan_code != 0 {
+            return match key.scan_code {
+                0x01 => Up,
+                0x02 => Down,
+                0x03 => Right,
+                0x04 => Left,
+                0x05 => Home,
+                0x06 => End,
+                0x07 => Insert,
+                0x08 => Delete,
+                0x09 => PageUp,
+                0x0a => PageDown,
+                0x0b..=0x16 => Function((key.scan_code - 0x0b + 1) as u8),
+                0x17 => Escape,
+                0x68..=0x73 => Function((key.scan_code - 0x68 + 13) as u8),
+                code => Unknown(code),
+            };
+        }
+
+        match key.unicode_char {
+            0x08 => Backspace,
+            0x09 => Tab,
+            0x0a | 0x0d => Enter,
+            0x1b => Escape,
+            chr => Char(char::from_u32(chr as u32)
+                .unwrap_or(core::char::REPLACEMENT_CHARACTER)),
+        }
+    }
+}
+
+/// Get a key press if one is available, without blocking
+///
+/// Returns `Ok(None)` if no key has been pressed
+pub fn poll_key() -> EfiResult<Option<Key>> {
+    let con_in = system_table()?.console_in;
+
+    let mut key = EfiInputKey::default();
+
+    let ret = unsafe { ((*con_in).read_keystroke)(con_in, &mut key) };
+
+    // `NOT_READY` means there is no key waiting
+    if ret == EfiStatus::NOT_READY { return Ok(None); }
+
+    ret.into_result()?;
+
+    Ok(Some(key.into()))
+}
+
+/// Get the event signaled when a key press is available, to combine key
+/// input with other events in `wait_for_event()`
+pub fn key_event() -> EfiResult<EfiEvent> {
+    Ok(unsafe { (*system_table()?.console_in).wait_for_key })
+}
+
+/// Wait for a key press and return it
+pub fn read_key() -> EfiResult<Key> {
+    let key_event = key_event()?;
+
+    loop {
+        if let Some(key) = poll_key()? {
+            return Ok(key);
+        }
+
+        wait_for_event(&[key_event])?;
+    }
+}
+
+/// Wait up to `timeout` for a key press
+///
+/// Returns `Ok(None)` if no key was pressed in time
+pub fn read_key_timeout(timeout: Duration) -> EfiResult<Option<Key>> {
+    let timer = Event::timer()?;
+    timer.set_timer(Timer::Relative(timeout))?;
+
+    let events = [key_event()?, timer.raw()];
+
+    loop {
+        if let Some(key) = poll_key()? {
+            return Ok(Some(key));
+        }
+
+        if wait_for_event(&events)? == 1 {
+            return Ok(None);
+        }
+    }
+}
+
+/// A line editor with backspace and history support
+///
+/// Up and down walk through the previously entered lines, Esc clears the
+/// current line.
+pub struct LineEditor {
+    /// Previously entered lines, oldest first
+    history: Vec<String>,
+
+    /// Maximum number of lines to keep in `history`
+    max_history: usize,
+}
+
+impl LineEditor {
+    /// Create a new line editor remembering up to `max_history` lines
+    pub fn new(max_history: usize) -> Self {
+        LineEditor {
+            history: Vec::new(),
+            max_history,
+        }
+    }
+
+    /// Get the previously entered lines, oldest first
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Display `prompt` and read a line from the console
+    ///
+    /// The returned line does not include the line terminator
+    pub fn read_line(&mut self, prompt: &str) -> EfiResult<String> {
+        output_string(prompt);
+
+        let mut line = String::new();
+
+        // Position in the history we are currently displaying, equal to the
+        // history length when editing a fresh line
+        let mut hist_idx = self.history.len();
+
+        loop {
+            match read_key()? {
+                Key::Enter => {
+                    output_string("\n");
+                    break;
+                }
+                Key::Backspace if line.pop().is_some() => {
+                    output_string("\u{8} \u{8}");
+                }
+                Key::Escape => {
+                    Self::replace_line(&mut line, "");
+                    hist_idx = self.history.len();
+                }
+                Key::Up if hist_idx > 0 => {
+                    hist_idx -= 1;
+                    Self::replace_line(&mut line, &self.history[hist_idx]);
+                }
+                Key::Down if hist_idx < self.history.len() => {
+                    hist_idx += 1;
+                    let new = self.history.get(hist_idx)
+                        .map(|line| line.as_str())
+                        .unwrap_or("");
+                    Self::replace_line(&mut line, new);
+                }
+                Key::Char(chr) if !chr.is_control() => {
+                    line.push(chr);
+
+                    let mut tmp = [0u8; 4];
+                    output_string(chr.encode_utf8(&mut tmp));
+                }
+                _ => {}
+            }
+        }
+
+        // Remember the line, skipping empty lines and repeats
+        if self.max_history > 0 && !line.is_empty() &&
+                self.history.last() != Some(&line) {
+            if self.history.len() == self.max_history {
+                self.history.remove(0);
+            }
+
+            self.history.push(line.clone());
+        }
+
+        Ok(line)
+    }
+
+    /// Erase `line` from the screen and replace it with `new`
+    fn replace_line(line: &mut String, new: &str) {
+        for _ in line.chars() {
+            output_string("\u{8} \u{8}");
+        }
+
+        line.clear();
+        line.push_str(new);
+        output_string(new);
+    }
+}
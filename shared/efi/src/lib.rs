@@ -2,21 +2,117 @@
 #![feature(abi_efiapi)]
 //! Rust EFI library
 
+extern crate alloc;
+
+mod allocator;
+mod config_table;
+mod console;
+mod event;
+mod fs;
+mod gop;
+mod guid;
+mod image;
+mod input;
+mod loaded_image;
+mod protocol;
+mod pxe;
+mod runtime;
+mod status;
+mod string;
+mod table;
+mod timing;
+
+pub use allocator::PoolAllocator;
+pub use config_table::*;
+pub use console::*;
+pub use event::*;
+pub use fs::*;
+pub use gop::*;
+pub use guid::*;
+pub use image::*;
+pub use input::*;
+pub use loaded_image::*;
+pub use protocol::*;
+pub use pxe::*;
+pub use runtime::*;
+pub use status::*;
+pub use string::*;
+pub use table::*;
+pub use timing::*;
+
 use core::sync::atomic::Ordering;
-use core::sync::atomic::AtomicPtr;
+use core::sync::atomic::{AtomicPtr, AtomicUsize};
 use core::fmt::{Result, Write};
 
 /// The standard Rust`efi_print!()` macro!
 #[macro_export]
 macro_rules! efi_print {
     ($($arg:tt)*) => {
-        let _ = <$crate::ScreenWriter as core::fmt::Write>::write_fmt(
-            &mut $crate::ScreenWriter,
+        $crate::efi_write!($crate::Console::Out, $($arg)*)
+    };
+}
+
+/// Like `efi_print!()`, with a newline appended
+#[macro_export]
+macro_rules! efi_println {
+    () => {
+        $crate::efi_print!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::efi_print!("{}\n", format_args!($($arg)*))
+    };
+}
+
+/// Like `efi_print!()`, but writes to the standard error console
+#[macro_export]
+macro_rules! efi_eprint {
+    ($($arg:tt)*) => {
+        $crate::efi_write!($crate::Console::Err, $($arg)*)
+    };
+}
+
+/// Like `efi_eprint!()`, with a newline appended
+#[macro_export]
+macro_rules! efi_eprintln {
+    () => {
+        $crate::efi_eprint!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::efi_eprint!("{}\n", format_args!($($arg)*))
+    };
+}
+
+/// Write formatted text to the `Console` `$console`
+#[macro_export]
+macro_rules! efi_write {
+    ($console:expr, $($arg:tt)*) => {
+        let _ = <$crate::ConsoleWriter as core::fmt::Write>::write_fmt(
+            &mut $crate::ConsoleWriter($console),
             format_args!($($arg)*)
         );
     };
 }
 
+/// Like `efi_print!()`, but with the foreground color `$color`. The previous
+/// colors are restored afterwards.
+#[macro_export]
+macro_rules! efi_print_colored {
+    ($color:expr, $($arg:tt)*) => {
+        $crate::with_color($crate::Console::Out, $color,
+            || { $crate::efi_print!($($arg)*); });
+    };
+}
+
+/// Create a `&'static CStr16` from a string literal, checked at compile time
+#[macro_export]
+macro_rules! cstr16 {
+    ($string:literal) => {{
+        const LEN: usize = $crate::ucs2_len($string) + 1;
+        const CHARS: [u16; LEN] = $crate::encode_ucs2::<LEN>($string);
+        unsafe { $crate::CStr16::from_u16_with_nul_unchecked(&CHARS) }
+    }};
+}
+
 /// Contains a table header and pointers to all of the boot services.
 #[repr(C)]
 pub struct EfiBootServices {
@@ -33,10 +129,18 @@ pub struct EfiBootServices {
     pub _restore_tpl: usize,
     
     /// Allocates pages of a particular type.
-    pub _allocate_pages: usize,
+    pub allocate_pages: unsafe extern "efiapi" fn(
+        typ:         EfiAllocateType,
+        memory_type: EfiMemoryType,
+        pages:       usize,
+        memory:      &mut u64,
+    ) -> EfiStatus,
 
     /// Frees allocated pages.
-    pub _free_pages: usize,
+    pub free_pages: unsafe extern "efiapi" fn(
+        memory: u64,
+        pages:  usize,
+    ) -> EfiStatus,
 
     /// Returns the courrent boot services memory map and memory map key.
     pub get_memory_map: unsafe extern "efiapi" fn(
@@ -48,76 +152,263 @@ pub struct EfiBootServices {
     ) -> EfiStatus,
 
     /// Allocates a pool of a particular type
-    pub _allocate_pool: usize,
+    pub allocate_pool: unsafe extern "efiapi" fn(
+        pool_type: EfiMemoryType,
+        size:      usize,
+        buffer:    &mut *mut u8,
+    ) -> EfiStatus,
 
     /// Frees allocated pool.
-    pub _free_pool: usize,
+    pub free_pool: unsafe extern "efiapi" fn(
+        buffer: *mut u8,
+    ) -> EfiStatus,
 
-    /// Creates a general-purpose even structure.
-    pub _create_event: usize,
+    /// Creates a general-purpose event structure.
+    pub create_event: unsafe extern "efiapi" fn(
+        typ:             u32,
+        notify_tpl:      EfiTpl,
+        notify_function: Option<EfiEventNotify>,
+        notify_context:  *mut u8,
+        event:           &mut EfiEvent,
+    ) -> EfiStatus,
 
     /// Sets an event to be signaled at a particular time.
-    pub _set_timer: usize,
+    pub set_timer: unsafe extern "efiapi" fn(
+        event:        EfiEvent,
+        typ:          EfiTimerDelay,
+        trigger_time: u64,
+    ) -> EfiStatus,
 
     /// Stops execution until an event is signaled.
-    pub _wait_for_event: usize,
+    pub wait_for_event: unsafe extern "efiapi" fn(
+        number_of_events: usize,
+        event:            *const EfiEvent,
+        index:            &mut usize,
+    ) -> EfiStatus,
 
     /// Signals an event.
-    pub _signal_event: usize,
+    pub signal_event: unsafe extern "efiapi" fn(
+        event: EfiEvent,
+    ) -> EfiStatus,
 
     /// Closes and frees an event structure.
-    pub _close_event: usize,
+    pub close_event: unsafe extern "efiapi" fn(
+        event: EfiEvent,
+    ) -> EfiStatus,
 
     /// Checks whether an event is in the signaled state.
-    pub _check_event: usize,
+    pub check_event: unsafe extern "efiapi" fn(
+        event: EfiEvent,
+    ) -> EfiStatus,
 
     /// Install a protocol interface on a device handle.
-    pub _install_protocol_interface: usize,
+    pub install_protocol_interface: unsafe extern "efiapi" fn(
+        handle:         &mut EfiHandle,
+        protocol:       *const EfiGuid,
+        interface_type: EfiInterfaceType,
+        interface:      *mut u8,
+    ) -> EfiStatus,
 
     /// Reinstalls a protocol interface on a device handle.
-    pub _reinstall_protocol_interface: usize,
+    pub reinstall_protocol_interface: unsafe extern "efiapi" fn(
+        handle:        EfiHandle,
+        protocol:      *const EfiGuid,
+        old_interface: *mut u8,
+        new_interface: *mut u8,
+    ) -> EfiStatus,
 
     /// Removes a protocol interface from a device handle.
-    pub _uninstall_protocol_interface: usize,
+    pub uninstall_protocol_interface: unsafe extern "efiapi" fn(
+        handle:    EfiHandle,
+        protocol:  *const EfiGuid,
+        interface: *mut u8,
+    ) -> EfiStatus,
 
     /// Queries a handle to determine if it supports a specified protocol.
-    pub _handle_protocol: usize,
+    pub handle_protocol: unsafe extern "efiapi" fn(
+        handle:    EfiHandle,
+        protocol:  *const EfiGuid,
+        interface: &mut *mut u8,
+    ) -> EfiStatus,
 
     /// Reserved
     pub _reserved: usize,
 
     /// Registers an event that is to be signaled whenever an interface is 
     /// installed for a specified protocol.
-    pub _register_protocol_notify: usize,
+    pub register_protocol_notify: unsafe extern "efiapi" fn(
+        protocol:     *const EfiGuid,
+        event:        EfiEvent,
+        registration: &mut *mut u8,
+    ) -> EfiStatus,
 
     /// Returns an array of handles that support a specified protocol.
-    pub _locate_handle: usize,
+    pub locate_handle: unsafe extern "efiapi" fn(
+        search_type: EfiLocateSearchType,
+        protocol:    *const EfiGuid,
+        search_key:  *const u8,
+        buffer_size: &mut usize,
+        buffer:      *mut EfiHandle,
+    ) -> EfiStatus,
 
     /// Locates all devices on a device path that support a specified protocol
     /// and returns the handle to the device that is closes to the path.
-    pub _locate_device_path: usize,
+    pub locate_device_path: unsafe extern "efiapi" fn(
+        protocol:    *const EfiGuid,
+        device_path: &mut *const EfiDevicePathProtocol,
+        device:      &mut EfiHandle,
+    ) -> EfiStatus,
 
     /// Adds, updates, or removes a configuration table from the EFI System
     /// Table.
-    pub _install_configuration_table: usize,
+    pub install_configuration_table: unsafe extern "efiapi" fn(
+        guid:  *const EfiGuid,
+        table: *mut u8,
+    ) -> EfiStatus,
 
     /// Loads an EFI image into memory.
-    pub _load_image: usize,
+    pub load_image: unsafe extern "efiapi" fn(
+        boot_policy:         bool,
+        parent_image_handle: EfiHandle,
+        device_path:         *const EfiDevicePathProtocol,
+        source_buffer:       *const u8,
+        source_size:         usize,
+        image_handle:        &mut EfiHandle,
+    ) -> EfiStatus,
 
-    /// Transfer control to a loaded image's entry poitn
-    pub _start_image: usize,
+    /// Transfer control to a loaded image's entry point.
+    pub start_image: unsafe extern "efiapi" fn(
+        image_handle:   EfiHandle,
+        exit_data_size: &mut usize,
+        exit_data:      &mut *mut u16,
+    ) -> EfiStatus,
 
     /// Exits the image's entry point.
-    pub _exit: usize,
+    pub exit: unsafe extern "efiapi" fn(
+        image_handle:   EfiHandle,
+        exit_status:    EfiStatus,
+        exit_data_size: usize,
+        exit_data:      *const u16,
+    ) -> EfiStatus,
 
-    /// Unloags an image.
-    pub _unload_image: usize,
+    /// Unloads an image.
+    pub unload_image: unsafe extern "efiapi" fn(
+        image_handle: EfiHandle,
+    ) -> EfiStatus,
 
     /// Terminates boot serviceds.
-    pub exit_boot_services: unsafe fn (
+    pub exit_boot_services: unsafe extern "efiapi" fn(
         image_handle: EfiHandle,
-        map_key:      usize,
+        map_key:      EfiMapKey,
+    ) -> EfiStatus,
+
+    /// Returns a monotonically increasing count for the platform.
+    pub get_next_monotonic_count: unsafe extern "efiapi" fn(
+        count: &mut u64,
+    ) -> EfiStatus,
+
+    /// Stalls the processor.
+    pub stall: unsafe extern "efiapi" fn(
+        microseconds: usize,
+    ) -> EfiStatus,
+
+    /// Resets and sets a watchdog timer used during boot services time.
+    pub set_watchdog_timer: unsafe extern "efiapi" fn(
+        timeout:       usize,
+        watchdog_code: u64,
+        data_size:     usize,
+        watchdog_data: *const u16,
+    ) -> EfiStatus,
+
+    /// Uses a set of precedence rules to find the best set of drivers to
+    /// manage a controller.
+    pub connect_controller: unsafe extern "efiapi" fn(
+        controller_handle:     EfiHandle,
+        driver_image_handle:   *const EfiHandle,
+        remaining_device_path: *const EfiDevicePathProtocol,
+        recursive:             bool,
+    ) -> EfiStatus,
+
+    /// Informs a set of drivers to stop managing a controller.
+    pub disconnect_controller: unsafe extern "efiapi" fn(
+        controller_handle:   EfiHandle,
+        driver_image_handle: EfiHandle,
+        child_handle:        EfiHandle,
+    ) -> EfiStatus,
+
+    /// Adds elements to the list of agents consuming a protocol interface.
+    pub open_protocol: unsafe extern "efiapi" fn(
+        handle:            EfiHandle,
+        protocol:          *const EfiGuid,
+        interface:         &mut *mut u8,
+        agent_handle:      EfiHandle,
+        controller_handle: EfiHandle,
+        attributes:        u32,
+    ) -> EfiStatus,
+
+    /// Removes elements from the list of agents consuming a protocol
+    /// interface.
+    pub close_protocol: unsafe extern "efiapi" fn(
+        handle:            EfiHandle,
+        protocol:          *const EfiGuid,
+        agent_handle:      EfiHandle,
+        controller_handle: EfiHandle,
+    ) -> EfiStatus,
+
+    /// Retrieve the list of agents that are currently consuming a protocol
+    /// interface.
+    pub open_protocol_information: unsafe extern "efiapi" fn(
+        handle:       EfiHandle,
+        protocol:     *const EfiGuid,
+        entry_buffer: &mut *mut EfiOpenProtocolInformationEntry,
+        entry_count:  &mut usize,
+    ) -> EfiStatus,
+
+    /// Retrieves the list of protocols installed on a handle. The return
+    /// buffer is automatically allocated.
+    pub protocols_per_handle: unsafe extern "efiapi" fn(
+        handle:                EfiHandle,
+        protocol_buffer:       &mut *mut *const EfiGuid,
+        protocol_buffer_count: &mut usize,
+    ) -> EfiStatus,
+
+    /// Retrieves the list of handles from the handle database that meet the
+    /// search criteria. The return buffer is automatically allocated.
+    pub locate_handle_buffer: unsafe extern "efiapi" fn(
+        search_type: EfiLocateSearchType,
+        protocol:    *const EfiGuid,
+        search_key:  *const u8,
+        no_handles:  &mut usize,
+        buffer:      &mut *mut EfiHandle,
+    ) -> EfiStatus,
+
+    /// Finds the first handle in the handle database the supports the
+    /// requested protocol.
+    pub locate_protocol: unsafe extern "efiapi" fn(
+        protocol:     *const EfiGuid,
+        registration: *const u8,
+        interface:    &mut *mut u8,
     ) -> EfiStatus,
+
+    /// Installs one or more protocol interfaces into the boot services
+    /// environment.
+    pub _install_multiple_protocol_interfaces: usize,
+
+    /// Removes one or more protocol interfaces into the boot services
+    /// environment.
+    pub _uninstall_multiple_protocol_interfaces: usize,
+
+    /// Computes and returns a 32-bit CRC for a data buffer.
+    pub _calculate_crc32: usize,
+
+    /// Copies the contents of one buffer to another buffer.
+    pub _copy_mem: usize,
+
+    /// Fills a buffer with a specified value.
+    pub _set_mem: usize,
+
+    /// Creates an event structure as part of an event group.
+    pub _create_event_ex: usize,
 }
 
 #[repr(C)]
@@ -135,19 +426,24 @@ pub struct EfiConfigurationTable {
     pub table: usize,
 }
 
-/// An Efi guid representation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(C)]
-#[derive(Debug)]
-pub struct EfiGuid {
-    pub data1: u32,
-    pub data2: u16,
-    pub data3: u16,
-    pub data4: [u8; 8],
+pub struct EfiHandle(usize);
+
+impl EfiHandle {
+    /// The null handle, used for optional handle arguments
+    pub const NULL: EfiHandle = EfiHandle(0);
 }
 
-#[derive(Clone, Copy, Debug)]
+/// An opaque handle to a firmware event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(C)]
-pub struct EfiHandle(usize);
+pub struct EfiEvent(usize);
+
+impl EfiEvent {
+    /// The null event, used where no event is expected
+    pub const NULL: EfiEvent = EfiEvent(0);
+}
 
 /// The memory descriptor for a record returned from `GetMemoryMap()`
 #[derive(Clone, Copy, Debug)]
@@ -183,6 +479,27 @@ pub struct EfiMemoryDescriptor {
 
 
 
+/// Memory attribute of regions which need a virtual mapping for runtime
+/// services once `SetVirtualAddressMap()` has been called
+pub const MEMORY_RUNTIME: u64 = 0x8000_0000_0000_0000;
+
+/// The type of allocation to perform with `AllocatePages()`
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub enum EfiAllocateType {
+    /// Allocate any available range of pages
+    AllocateAnyPages,
+
+    /// Allocate any available range of pages whose uppermost address is less
+    /// than or equal to the address passed in
+    AllocateMaxAddress,
+
+    /// Allocate pages at the address passed in
+    AllocateAddress,
+
+    MaxAllocateType,
+}
+
 /// EFI memory types
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
@@ -242,73 +559,109 @@ impl From<u32> for EfiMemoryType {
     }
 }
 
+/// The console output devices which can be written to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Console {
+    /// The console output device
+    Out,
+
+    /// The standard error device
+    Err,
+
+    /// Both the console output and the standard error devices
+    Both,
+}
+
 /// Write a `string` to the UEFI console output
 pub fn output_string(string: &str) {
+    write_console(Console::Out, string);
+}
+
+/// Writer used for console output once boot services have been exited, as a
+/// `fn(&str)` pointer. Zero if none has been registered.
+static CONSOLE_FALLBACK: AtomicUsize = AtomicUsize::new(0);
+
+/// Register `writer` to receive all console output once boot services have
+/// been exited, which keeps `efi_print!` and friends working after the
+/// handoff. Both `Console::Out` and `Console::Err` go to `writer`.
+pub fn register_console_fallback(writer: fn(&str)) {
+    CONSOLE_FALLBACK.store(writer as usize, Ordering::SeqCst);
+}
+
+/// Write a `string` to the UEFI console output device(s) `console`
+pub fn write_console(console: Console, string: &str) {
     // Get the system table
     let st = EFI_SYSTEM_TABLE.load(Ordering::SeqCst);
 
-    // We can't do anything if it is null
-    if st.is_null() { return; }
-
-    // Get the console out pointer
-    let out = unsafe {
-        (*st).console_out
-    };
+    // Without boot services, hand the output to the fallback writer if any
+    if st.is_null() {
+        let writer = CONSOLE_FALLBACK.load(Ordering::SeqCst);
 
-    // Create a temporary buffer capable of holdoing 31 characters at a time
-    // plus a null terminator.
-    //
-    // We are using UCS-2 and not UTF-16, as that's what UEFI used. Thus, we 
-    // don't have to worry about 32-but code points
-    let mut tmp = [0_u16; 32];
-    let mut in_use = 0;
-
-    for chr in string.encode_utf16() {
-        // Inject carriage return if needed. We always make sure there's room
-        // for one based on the way we check the buffer length (-2 instead of 
-        // -1)
-        if chr == b'\n' as u16 {
-            tmp[in_use] = b'\r' as u16;
-            in_use += 1;
+        if writer != 0 {
+            let writer: fn(&str) = unsafe { core::mem::transmute(writer) };
+            writer(string);
         }
 
-        // Write a character into the buffer
-        tmp[in_use] = chr;
-        in_use += 1;
+        return;
+    }
 
-        if in_use == (tmp.len() - 2) {
-            // Null terminate the buffer
-            tmp[in_use] = 0;
+    let (out, err) = unsafe {
+        ((*st).console_out, (*st).console_err)
+    };
 
-            // Write out the buffer
-            unsafe {
-                ((*out).output_string)(out, tmp.as_ptr());
+    match console {
+        Console::Out => output_string_to(out, string),
+        Console::Err => output_string_to(err, string),
+        Console::Both => {
+            output_string_to(out, string);
 
-                // Clear the buffer
-                in_use = 0;
-            }
+            // Firmware commonly points both at the same device, don't print
+            // everything twice in that case
+            if err != out { output_string_to(err, string); }
         }
     }
+}
+
+/// Write a `string` to the text output protocol `out`
+fn output_string_to(out: *const EfiSimpleTextOutputProtocol, string: &str) {
+    // Some firmware doesn't provide a standard error device
+    if out.is_null() { return; }
+
+    // Convert in chunks on the stack, console output has to keep working
+    // when allocations fail
+    let mut buf = [0u16; 32];
+
+    // The firmware expects `\r\n` line endings
+    for (idx, line) in string.split('\n').enumerate() {
+        if idx > 0 { output_cstr16_to(out, cstr16!("\r\n")); }
+
+        let mut rest = line;
 
-    // Write out any remaining characters
-    if in_use > 0 {
-        // Null terminate the buffer
-        tmp[in_use] = 0;
+        while !rest.is_empty() {
+            let (chunk, remaining) = CStr16::from_str_lossy_with_buf(rest,
+                &mut buf);
 
-        unsafe {
-            ((*out).output_string)(out, tmp.as_ptr());
+            output_cstr16_to(out, chunk);
+            rest = remaining;
         }
     }
 }
 
+/// Write the UCS-2 `string` to the text output protocol `out`
+fn output_cstr16_to(out: *const EfiSimpleTextOutputProtocol,
+        string: &CStr16) {
+    unsafe { ((*out).output_string)(out, string.as_ptr()); }
+}
+
 /// A scan code and unicode value for a input keypress
+#[derive(Clone, Copy, Debug, Default)]
 #[repr(C)]
 pub struct EfiInputKey {
     /// The scan code for the key press
-    scan_code: u16,
+    pub scan_code: u16,
 
     /// The unicode representation of the key
-    unicode_char: u16,
+    pub unicode_char: u16,
 }
 
 /// This protocol is used ot obtain input form the ConsoleIn device. The EFI
@@ -317,176 +670,110 @@ pub struct EfiInputKey {
 #[repr(C)]
 pub struct EfiSimpleTextInputProtocol {
     /// Resets the input device hardware.
-    pub reset: unsafe fn(
+    pub reset: unsafe extern "efiapi" fn(
         this: *const EfiSimpleTextInputProtocol,
         extended_verification: bool
     ) -> EfiStatus,
 
     /// Reads the next keystroke from the input device.
-    pub read_keystroke: unsafe fn(
+    pub read_keystroke: unsafe extern "efiapi" fn(
         this: *const EfiSimpleTextInputProtocol,
         key: *mut EfiInputKey
     ) -> EfiStatus,
 
     /// Evento to use with EFI_BOOT_SERVICES.WaitForEvent() to wait for a key to
     /// to be available.
-    /// We don't use the event API thus we don't expose this function pointer
-    pub  _wait_for_key: usize,
+    pub wait_for_key: EfiEvent,
 }
 
 /// This protocol is used to control text-based output devices..
 #[repr(C)]
 pub struct EfiSimpleTextOutputProtocol {
     /// Resets the text output device hardware.
-    pub reset: unsafe fn(
+    pub reset: unsafe extern "efiapi" fn(
         this: *const EfiSimpleTextOutputProtocol,
         extended_verification: bool,
     ) -> EfiStatus,
 
     /// Writes a string to the output device.
-    pub output_string: unsafe fn (
+    pub output_string: unsafe extern "efiapi" fn(
         this: *const EfiSimpleTextOutputProtocol,
         string: *const u16,
     ) -> EfiStatus,
 
     /// Verifies that all carachters in a string can be output to the target
     /// device.
-    pub test_string: unsafe fn(
+    pub test_string: unsafe extern "efiapi" fn(
         this: *const EfiSimpleTextOutputProtocol,
         string: *const u16,
     ) -> EfiStatus,
 
     /// Returns information for an available text mode that the output
     /// device(s) supports.
-    pub _query_mode: usize,
+    pub query_mode: unsafe extern "efiapi" fn(
+        this:        *const EfiSimpleTextOutputProtocol,
+        mode_number: usize,
+        columns:     &mut usize,
+        rows:        &mut usize,
+    ) -> EfiStatus,
 
     /// Sets the output device(s) to a specified mode.
-    pub _set_mode: usize,
+    pub set_mode: unsafe extern "efiapi" fn(
+        this:        *const EfiSimpleTextOutputProtocol,
+        mode_number: usize,
+    ) -> EfiStatus,
 
     /// Sets the background and foreground colors for the OutputString() and
     /// ClearScreen() functions.
-    pub _set_attribute: usize,
+    pub set_attribute: unsafe extern "efiapi" fn(
+        this:      *const EfiSimpleTextOutputProtocol,
+        attribute: usize,
+    ) -> EfiStatus,
 
     /// Clears the output device(s) display to the currently selected
     /// background color
-    pub _clear_screen: usize,
+    pub clear_screen: unsafe extern "efiapi" fn(
+        this: *const EfiSimpleTextOutputProtocol,
+    ) -> EfiStatus,
 
     /// Sets the current coordinates of the cursor position.
-    pub _set_cursor_position: usize,
-    
+    pub set_cursor_position: unsafe extern "efiapi" fn(
+        this:   *const EfiSimpleTextOutputProtocol,
+        column: usize,
+        row:    usize,
+    ) -> EfiStatus,
+
     /// Make the cursord visibile or invisible.
-    pub _enable_cursor: usize,
+    pub enable_cursor: unsafe extern "efiapi" fn(
+        this:    *const EfiSimpleTextOutputProtocol,
+        visible: bool,
+    ) -> EfiStatus,
 
     /// Pointer to SIMPLE_TEXT_OUTPUT_MODE data.
-    pub _mode: usize,
+    pub mode: *const EfiSimpleTextOutputMode,
 }
 
-// Status code.
-#[derive(Clone, Copy, Debug, PartialEq)]
+/// The current state of a text output device
+#[derive(Clone, Copy, Debug)]
 #[repr(C)]
-pub enum EfiStatus{
-    EfiSuccess,
-    EfiLoadError,
-    EfiInvalidParameter,
-    EfiUnsupported,
-    EfiBadBufferSize,
-    EfiBufferTooSmall,
-    EfiNotReady,
-    EfiDeviceError,
-    EfiWriteProtected,
-    EfiOutOfResources,
-    EfiVolumeCorrupted,
-    EfiVolumeFull,
-    EfiNoMedia,
-    EfiMediaChanged,
-    EfiNotFound,
-    EfiAccessDenied,
-    EfiNoResponse,
-    EfiNoMapping,
-    EfiTimeout,
-    EfiNotStarted,
-    EfiAlreadyStarted,
-    EfiAborted,
-    EfiIcmpError,
-    EfiTftpError,
-    EfiProtocolError,
-    EfiIncompatibleVersion,
-    EfiSecurityViolation,
-    EfiCrcError,
-    EfiEndOfMedia,
-    EfiEndOfFile,
-    EfiInvalidLanguage,
-    EfiCompromisedData,
-    EfiHttpError,
-    EfiNetworkUnreachable,
-    EfiHostUnreachable,
-    EfiProtocolUnreachable,
-    EfiPortUnreachable,
-    EfiConnectioNFin,
-    EfiConnectionReset,
-    EfiConnectionRefused,
-    EfiWarnUnknownGlyph,
-    EfiWarnDeleteFailure,
-    EfiWarnWriteFailure,
-    EfiWarnBufferTooSmall,
-    EfiWarnStaleData,
-    EfiWarnFileSystem,
-    InvalidEfiStatusCode(usize),
-}
-
-impl From<usize> for EfiStatus {
-    fn from(val: usize) -> Self {
-        use EfiStatus::*;
-        match val {
-            0x0 => EfiSuccess,
-            0x8000000000000001 => EfiLoadError,
-            0x8000000000000002 => EfiInvalidParameter,
-            0x8000000000000003 => EfiUnsupported,
-            0x8000000000000004 => EfiBadBufferSize,
-            0x8000000000000005 => EfiBufferTooSmall,
-            0x8000000000000006 => EfiNotReady,
-            0x8000000000000007 => EfiDeviceError,
-            0x8000000000000008 => EfiWriteProtected,
-            0x8000000000000009 => EfiOutOfResources,
-            0x800000000000000a => EfiVolumeCorrupted,
-            0x800000000000000b => EfiVolumeFull,
-            0x800000000000000c => EfiNoMedia,
-            0x800000000000000d => EfiMediaChanged,
-            0x800000000000000e => EfiNotFound,
-            0x800000000000000f => EfiAccessDenied,
-            0x8000000000000010 => EfiNoResponse,
-            0x8000000000000011 => EfiNoMapping,
-            0x8000000000000012 => EfiTimeout,
-            0x8000000000000013 => EfiNotStarted,
-            0x8000000000000014 => EfiAlreadyStarted,
-            0x8000000000000015 => EfiAborted,
-            0x8000000000000016 => EfiIcmpError,
-            0x8000000000000017 => EfiTftpError,
-            0x8000000000000018 => EfiProtocolError,
-            0x8000000000000019 => EfiIncompatibleVersion,
-            0x800000000000001a => EfiSecurityViolation,
-            0x800000000000001b => EfiCrcError,
-            0x800000000000001c => EfiEndOfMedia,
-            0x800000000000001f => EfiEndOfFile,
-            0x8000000000000020 => EfiInvalidLanguage,
-            0x8000000000000021 => EfiCompromisedData,
-            0x8000000000000023 => EfiHttpError,
-            0x8000000000000064 => EfiNetworkUnreachable,
-            0x8000000000000065 => EfiHostUnreachable,
-            0x8000000000000066 => EfiProtocolUnreachable,
-            0x8000000000000067 => EfiPortUnreachable,
-            0x8000000000000068 => EfiConnectioNFin,
-            0x8000000000000069 => EfiConnectionReset,
-            0x800000000000006a => EfiConnectionRefused,
-            0x1 => EfiWarnUnknownGlyph,
-            0x2 => EfiWarnDeleteFailure,
-            0x3 => EfiWarnWriteFailure,
-            0x4 => EfiWarnBufferTooSmall,
-            0x5 => EfiWarnStaleData,
-            0x6 => EfiWarnFileSystem,
-            _ => InvalidEfiStatusCode(val),
-        }
-    }
+pub struct EfiSimpleTextOutputMode {
+    /// The number of modes supported by QueryMode() and SetMode().
+    pub max_mode: i32,
+
+    /// The text mode of the output device(s).
+    pub mode: i32,
+
+    /// The current character output attribute.
+    pub attribute: i32,
+
+    /// The cursor's column.
+    pub cursor_column: i32,
+
+    /// The cursor's row.
+    pub cursor_row: i32,
+
+    /// The cursor is currently visible or not.
+    pub cursor_visible: bool,
 }
 
 /// A pointer to the EFI system table which is saved upon the entry of the
@@ -497,11 +784,16 @@ pub(crate) static EFI_SYSTEM_TABLE: AtomicPtr<EfiSystemTable> =
     AtomicPtr::new(core::ptr::null_mut());
 
 
-/// Register a system table pointer. This of course is unsafe as it requires 
-/// the caller to provide a valid EFI system table pointer.
+/// Register a system table pointer.
 ///
 /// Only the first non-null system table will be stored into the
-/// `EFI_SYSTEM_TABLE` global
+/// `EFI_SYSTEM_TABLE` global. The runtime services table and configuration
+/// tables it points to are saved as well, as they outlive boot services.
+///
+/// # Safety
+///
+/// The caller must provide a valid EFI system table pointer, such as the
+/// one passed to the entry point.
 pub unsafe fn register_system_table(system_table: *mut EfiSystemTable) {
     EFI_SYSTEM_TABLE.compare_exchange(
         core::ptr::null_mut(), 
@@ -509,6 +801,48 @@ pub unsafe fn register_system_table(system_table: *mut EfiSystemTable) {
         Ordering::SeqCst,
         Ordering::SeqCst,
     ).expect("Could not register the system table");
+
+    EFI_RUNTIME_SERVICES.store(
+        (*system_table).runtime_services as *mut EfiRuntimeServices,
+        Ordering::SeqCst);
+    config_table::save_configuration_tables(&*system_table);
+}
+
+/// The handle of the running image, zero until registered
+static EFI_IMAGE_HANDLE: AtomicUsize = AtomicUsize::new(0);
+
+/// Register the handle of the running image as passed to the entry point.
+/// The handle is used as the agent when opening protocols.
+pub fn register_image_handle(image: EfiHandle) {
+    EFI_IMAGE_HANDLE.store(image.0, Ordering::SeqCst);
+}
+
+/// Get the handle of the running image
+///
+/// Fails with `EfiStatus::NOT_READY` if no image handle has been registered
+pub fn image_handle() -> EfiResult<EfiHandle> {
+    match EFI_IMAGE_HANDLE.load(Ordering::SeqCst) {
+        0      => Err(EfiError(EfiStatus::NOT_READY)),
+        handle => Ok(EfiHandle(handle)),
+    }
+}
+
+/// Get the registered system table
+///
+/// Fails with `EfiStatus::UNSUPPORTED` if no system table has been registered
+/// or boot services have been exited
+pub(crate) fn system_table() -> EfiResult<&'static EfiSystemTable> {
+    let st = EFI_SYSTEM_TABLE.load(Ordering::SeqCst);
+
+    // We can't do anything if it is null
+    if st.is_null() { return Err(EfiError(EfiStatus::UNSUPPORTED)); }
+
+    Ok(unsafe { &*st })
+}
+
+/// Get the boot services table of the registered system table
+pub(crate) fn boot_services() -> EfiResult<&'static EfiBootServices> {
+    Ok(unsafe { &*system_table()?.boot_services })
 }
 
 /// Contains pointers to the runtime and boot services tables.
@@ -552,7 +886,7 @@ pub struct EfiSystemTable {
     pub console_err: *const EfiSimpleTextOutputProtocol,
 
     /// A pointer to the EFI Runtime Services Table.
-    pub _runtime_services: usize,
+    pub runtime_services: *const EfiRuntimeServices,
 
     /// A pointer to the EFI Boot Services Table.
     pub boot_services: *const EfiBootServices,
@@ -564,52 +898,461 @@ pub struct EfiSystemTable {
     pub tables: *const EfiConfigurationTable,
 }
 
-pub fn get_memory_map() {
-    // TODO!
-    let st = EFI_SYSTEM_TABLE.load(Ordering::SeqCst);
+impl EfiSystemTable {
+    /// Get the name of the firmware vendor, empty if the firmware doesn't
+    /// provide one
+    pub fn firmware_vendor(&self) -> &CStr16 {
+        if self.firmware_vendor.is_null() { return cstr16!(""); }
+
+        unsafe { CStr16::from_ptr(self.firmware_vendor) }
+    }
+}
+
+/// Where `allocate_pages()` is allowed to place an allocation
+#[derive(Clone, Copy, Debug)]
+pub enum AllocateType {
+    /// Anywhere in memory
+    AnyPages,
+
+    /// Anywhere, as long as the last byte is at or below the given address
+    MaxAddress(u64),
+
+    /// Exactly at the given page-aligned address
+    Address(u64),
+}
+
+/// Allocate `pages` 4KiB pages of memory of type `typ`
+///
+/// Returns the physical address of the first page
+pub fn allocate_pages(alloc: AllocateType, typ: EfiMemoryType, pages: usize)
+        -> EfiResult<u64> {
+    let boot_services = boot_services()?;
+
+    // Get the allocation type and the address the firmware should use as
+    // input
+    let (alloc_type, mut addr) = match alloc {
+        AllocateType::AnyPages =>
+            (EfiAllocateType::AllocateAnyPages, 0),
+        AllocateType::MaxAddress(max) =>
+            (EfiAllocateType::AllocateMaxAddress, max),
+        AllocateType::Address(addr) =>
+            (EfiAllocateType::AllocateAddress, addr),
+    };
+
+    unsafe {
+        (boot_services.allocate_pages)(alloc_type, typ, pages, &mut addr)
+    }.into_result()?;
 
-    if st.is_null() { return; }
+    Ok(addr)
+}
+
+/// Free `pages` 4KiB pages starting at `addr`
+///
+/// Does nothing once boot services have been exited.
+///
+/// # Safety
+///
+/// The caller must make sure the pages were allocated with
+/// `allocate_pages()` and are not referenced anymore.
+pub unsafe fn free_pages(addr: u64, pages: usize) -> EfiResult<()> {
+    match boot_services() {
+        Ok(boot_services) => (boot_services.free_pages)(addr, pages)
+            .into_result(),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Allocate `size` bytes from the pool of type `typ`
+///
+/// The returned buffer is 8-byte aligned
+pub fn allocate_pool(typ: EfiMemoryType, size: usize) -> EfiResult<*mut u8> {
+    let boot_services = boot_services()?;
 
-    // // Create an empty memory map
-    let mut memory_map = [0u8; 4 * 1024];
+    let mut buffer = core::ptr::null_mut();
 
-    let mut free_memory = 0u64;
     unsafe {
-        let mut size = core::mem::size_of_val(&memory_map);
-        let mut key = EfiMapKey(0);
-        let mut mdesc_size = 0;
-        let mut mdesc_version = 0;
+        (boot_services.allocate_pool)(typ, size, &mut buffer)
+    }.into_result()?;
+
+    Ok(buffer)
+}
+
+/// Free a buffer allocated with `allocate_pool()`
+///
+/// Does nothing once boot services have been exited.
+///
+/// # Safety
+///
+/// The caller must make sure `buffer` came from `allocate_pool()` and is not
+/// referenced anymore.
+pub unsafe fn free_pool(buffer: *mut u8) -> EfiResult<()> {
+    match boot_services() {
+        Ok(boot_services) => (boot_services.free_pool)(buffer).into_result(),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Number of extra descriptors to leave room for when allocating the memory
+/// map buffer. Allocating the buffer itself may split a free region and add
+/// descriptors to the map, so we always ask for a bit more than the firmware
+/// told us it needs.
+const MEMORY_MAP_SLACK_DESCRIPTORS: usize = 8;
+
+/// A snapshot of the UEFI memory map as returned by `GetMemoryMap()`
+///
+/// The descriptors are kept in their raw firmware layout, as the descriptor
+/// size reported by the firmware may be larger than `EfiMemoryDescriptor`.
+/// Use `iter()` to walk the parsed entries.
+///
+/// The descriptor buffer is allocated from the boot services pool as
+/// `LoaderData`, thus it stays valid after boot services have been exited.
+pub struct MemoryMap {
+    /// Raw descriptor bytes as filled in by the firmware
+    buffer: *mut u8,
+
+    /// Number of bytes of `buffer` which hold valid descriptors
+    size: usize,
+
+    /// Number of bytes allocated for `buffer`
+    capacity: usize,
+
+    /// The key identifying this version of the memory map
+    key: EfiMapKey,
+
+    /// Size, in bytes, of a single descriptor in `buffer`
+    descriptor_size: usize,
+
+    /// Version of the descriptor layout reported by the firmware
+    descriptor_version: u32,
+}
+
+impl MemoryMap {
+    /// Get the key identifying this version of the memory map. This is the
+    /// key which must be passed to `ExitBootServices()`
+    pub fn key(&self) -> EfiMapKey {
+        self.key
+    }
+
+    /// Get the size, in bytes, of a single memory descriptor
+    pub fn descriptor_size(&self) -> usize {
+        self.descriptor_size
+    }
+
+    /// Get the version of the memory descriptor layout
+    pub fn descriptor_version(&self) -> u32 {
+        self.descriptor_version
+    }
+
+    /// Get the number of descriptors in the memory map
+    pub fn len(&self) -> usize {
+        self.size / self.descriptor_size
+    }
+
+    /// Returns `true` if the memory map contains no descriptors
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get an iterator over the parsed entries of the memory map
+    pub fn iter(&self) -> MemoryMapIter<'_> {
+        MemoryMapIter {
+            map:    self,
+            offset: 0,
+        }
+    }
+
+    /// Get the total number of bytes which will be available for use once
+    /// boot services have been exited
+    pub fn free_memory(&self) -> u64 {
+        self.iter()
+            .filter(|entry| entry.typ.avail_post_exit_boot_services())
+            .map(|entry| entry.size())
+            .sum()
+    }
+
+    /// Assign virtual addresses to all regions which must stay mapped for
+    /// runtime services
+    ///
+    /// The regions are packed one after another starting at `base`, keeping
+    /// their order in the map. Other regions are left untouched. Returns the
+    /// address right past the last assigned region, this range must be
+    /// mapped by the kernel before runtime services are used.
+    pub fn layout_runtime_regions(&mut self, base: u64) -> u64 {
+        let mut next = base;
 
-        let ret = ((*(*st).boot_services).get_memory_map)(
+        for offset in (0..self.size).step_by(self.descriptor_size) {
+            let desc = unsafe {
+                self.buffer.add(offset) as *mut EfiMemoryDescriptor
+            };
+
+            let entry: MemoryMapEntry = unsafe {
+                core::ptr::read_unaligned(desc)
+            }.into();
+
+            if !entry.is_runtime() { continue; }
+
+            unsafe {
+                core::ptr::addr_of_mut!((*desc).virtual_start)
+                    .write_unaligned(next);
+            }
+
+            next += entry.size();
+        }
+
+        next
+    }
+
+    /// Translate the physical address `phys` inside a runtime region to the
+    /// virtual address assigned to it
+    pub fn runtime_virtual_address(&self, phys: u64) -> Option<u64> {
+        self.iter()
+            .filter(|entry| entry.is_runtime())
+            .find(|entry| phys >= entry.physical_start &&
+                phys - entry.physical_start < entry.size())
+            .map(|entry| entry.virtual_start + (phys - entry.physical_start))
+    }
+}
+
+impl<'a> IntoIterator for &'a MemoryMap {
+    type Item = MemoryMapEntry;
+    type IntoIter = MemoryMapIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// A parsed entry from the memory map
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryMapEntry {
+    /// Type of the memory region
+    pub typ: EfiMemoryType,
+
+    /// Physical address of the first byte in the memory region
+    pub physical_start: u64,
+
+    /// Virtual address of the first byte in the memory region
+    pub virtual_start: u64,
+
+    /// Number of 4KiB pages in the memory region
+    pub number_of_pages: u64,
+
+    /// Capabilities of the memory region
+    pub attribute: u64,
+}
+
+impl MemoryMapEntry {
+    /// Get the size of the memory region in bytes
+    pub fn size(&self) -> u64 {
+        self.number_of_pages * 4096
+    }
+
+    /// Returns `true` if the region must be mapped for runtime services
+    pub fn is_runtime(&self) -> bool {
+        (self.attribute & MEMORY_RUNTIME) != 0
+    }
+}
+
+impl From<EfiMemoryDescriptor> for MemoryMapEntry {
+    fn from(desc: EfiMemoryDescriptor) -> Self {
+        MemoryMapEntry {
+            typ:             desc.typ.into(),
+            physical_start:  desc.physical_start,
+            virtual_start:   desc.virtual_start,
+            number_of_pages: desc.number_of_pages,
+            attribute:       desc.attribute,
+        }
+    }
+}
+
+/// An iterator over the entries of a `MemoryMap`
+pub struct MemoryMapIter<'a> {
+    /// The memory map we're iterating over
+    map: &'a MemoryMap,
+
+    /// Byte offset of the next descriptor to parse
+    offset: usize,
+}
+
+impl<'a> Iterator for MemoryMapIter<'a> {
+    type Item = MemoryMapEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Make sure there's a whole descriptor left
+        if self.offset + self.map.descriptor_size > self.map.size {
+            return None;
+        }
+
+        // Descriptors are `descriptor_size` apart which is not necessarily
+        // the size of our structure, so read them unaligned
+        let entry = unsafe {
+            core::ptr::read_unaligned(
+                self.map.buffer.add(self.offset) as *const EfiMemoryDescriptor
+            )
+        };
+
+        self.offset += self.map.descriptor_size;
+
+        Some(entry.into())
+    }
+}
+
+impl Drop for MemoryMap {
+    fn drop(&mut self) {
+        // Once boot services are gone the pool is owned by the kernel and
+        // this does nothing
+        let _ = unsafe { free_pool(self.buffer) };
+    }
+}
+
+/// Get the current memory map from the firmware
+///
+/// The buffer holding the map is grown as needed until the firmware is able
+/// to return the whole map.
+pub fn get_memory_map() -> EfiResult<MemoryMap> {
+    let boot_services = boot_services()?;
+
+    // Ask the firmware how big the memory map is by handing it an empty
+    // buffer
+    let mut size = 0;
+    let mut key = EfiMapKey(0);
+    let mut descriptor_size = 0;
+    let mut descriptor_version = 0;
+
+    let ret = unsafe {
+        (boot_services.get_memory_map)(
             &mut size,
-            memory_map.as_mut_ptr() as *mut EfiMemoryDescriptor,
+            core::ptr::null_mut(),
             &mut key,
-            &mut mdesc_size,
-            &mut mdesc_version
-        );
+            &mut descriptor_size,
+            &mut descriptor_version
+        )
+    };
 
-        assert!(ret == EfiStatus::EfiSuccess, "Error {:x?} while getting the memory map", ret);
+    if ret != EfiStatus::BUFFER_TOO_SMALL {
+        // Anything but an error here means the firmware reported an empty
+        // map, which is as broken as an error
+        ret.into_result()?;
+        return Err(EfiError(EfiStatus::BAD_BUFFER_SIZE));
+    }
+
+    loop {
+        // Allocate a buffer big enough for the map, with some room for the
+        // descriptors our own allocation might add
+        let capacity = size + MEMORY_MAP_SLACK_DESCRIPTORS * descriptor_size;
+        let buffer = allocate_pool(EfiMemoryType::LoaderData, capacity)?;
+
+        // From here on the buffer is released if we have to bail out
+        let mut map = MemoryMap {
+            buffer,
+            size: capacity,
+            capacity,
+            key: EfiMapKey(0),
+            descriptor_size: 0,
+            descriptor_version: 0,
+        };
+
+        let ret = unsafe {
+            (boot_services.get_memory_map)(
+                &mut map.size,
+                map.buffer as *mut EfiMemoryDescriptor,
+                &mut map.key,
+                &mut map.descriptor_size,
+                &mut map.descriptor_version
+            )
+        };
+
+        if ret == EfiStatus::BUFFER_TOO_SMALL {
+            // The map grew between the two calls, `map.size` now holds the
+            // new required size. Drop this buffer and try again.
+            size = map.size;
+            descriptor_size = map.descriptor_size;
+            continue;
+        }
+
+        ret.into_result()?;
+
+        // Descriptors can be larger than our structure but never smaller,
+        // walking the map relies on this
+        if map.descriptor_size < core::mem::size_of::<EfiMemoryDescriptor>() {
+            return Err(EfiError(EfiStatus::UNSUPPORTED));
+        }
+
+        return Ok(map);
+    }
+}
 
-        for off in (0..size).step_by(mdesc_size) {
-            let entry = core::ptr::read_unaligned(
-                memory_map[off..].as_ptr() as *const EfiMemoryDescriptor
-            );
-            let typ: EfiMemoryType = entry.typ.into();
+/// Number of times we retry `ExitBootServices()` when the memory map changed
+/// under us before giving up
+const EXIT_BOOT_SERVICES_RETRIES: usize = 8;
 
-            if typ.avail_post_exit_boot_services() {
-                free_memory += entry.number_of_pages * 4096;
+/// Exit boot services and take ownership of the memory map
+///
+/// A fresh memory map is fetched right before calling `ExitBootServices()`.
+/// If the firmware reports that the map key went stale (the map changed
+/// between the two calls) the map is fetched again and the exit is retried.
+///
+/// After a failed `ExitBootServices()` only `GetMemoryMap()` may be called,
+/// so retries refill the buffer of the first map, which has room for a few
+/// more descriptors, instead of allocating a new one.
+///
+/// On success the registered system table is cleared, thus `efi_print!` and
+/// every other wrapper relying on boot services become no-ops. The returned
+/// map is the final memory map and is owned by the caller.
+pub fn exit_boot_services(image: EfiHandle) -> EfiResult<MemoryMap> {
+    let boot_services = boot_services()?;
+
+    // The map must not be freed before boot services are exited, freeing is
+    // a pool call as well
+    let mut map = core::mem::ManuallyDrop::new(get_memory_map()?);
+
+    let mut ret = EfiStatus::INVALID_PARAMETER;
+
+    for attempt in 0..EXIT_BOOT_SERVICES_RETRIES {
+        if attempt > 0 {
+            // Get the latest memory map and its key into the same buffer
+            let map: &mut MemoryMap = &mut map;
+            map.size = map.capacity;
+
+            ret = unsafe {
+                (boot_services.get_memory_map)(
+                    &mut map.size,
+                    map.buffer as *mut EfiMemoryDescriptor,
+                    &mut map.key,
+                    &mut map.descriptor_size,
+                    &mut map.descriptor_version
+                )
+            };
+
+            // The map outgrew the buffer, we can't allocate a bigger one
+            if ret.is_error() { break; }
+        }
+
+        ret = unsafe {
+            (boot_services.exit_boot_services)(image, map.key())
+        };
+
+        if !ret.is_error() {
+            // Tables may have been added since the system table was
+            // registered, keep the final list around
+            if let Ok(st) = system_table() {
+                config_table::save_configuration_tables(st);
             }
 
-            efi_print!("{:016x} {:016x} {:?}\n",
-                entry.physical_start,
-                entry.number_of_pages * 4096,
-                typ
-            );
+            // Boot services are gone, make sure nobody calls into them
+            // anymore
+            EFI_SYSTEM_TABLE.store(core::ptr::null_mut(), Ordering::SeqCst);
+
+            return Ok(core::mem::ManuallyDrop::into_inner(map));
         }
+
+        // Anything but a stale map key is fatal
+        if ret != EfiStatus::INVALID_PARAMETER { break; }
     }
 
-    // //efi_print!("Total bytes free {}\n", free_memory);
-    // free_memory
+    // The memory map kept changing or the firmware refused to exit. The
+    // buffer is leaked as the pool can't be used anymore.
+    Err(EfiError(ret))
 }
 
 /// Data structure that precedes all of the standard EFI table types.
@@ -651,12 +1394,13 @@ pub struct EfiTableHeader {
     pub reserved: u32,
 }
 
-/// A dummy screen writing structure we can implement `Write` on
-pub struct ScreenWriter;
+/// A dummy screen writing structure we can implement `Write` on, writing to
+/// the selected console output device(s)
+pub struct ConsoleWriter(pub Console);
 
-impl Write for ScreenWriter {
+impl Write for ConsoleWriter {
     fn write_str(&mut self, string: &str) -> Result {
-        output_string(string);
+        write_console(self.0, string);
         Ok(())
     }
 }
\ No newline at end of file
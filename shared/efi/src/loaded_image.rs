@@ -0,0 +1,141 @@
+//! Loaded Image Protocol: where the running image lives and how it was
+//! started
+
+use alloc::string::String;
+use crate::{image_handle, open, CString16, EfiDevicePathProtocol, EfiGuid};
+use crate::EfiHandle;
+use crate::{EfiMemoryType, EfiResult, EfiSystemTable, Protocol};
+
+/// GUID of the Loaded Image Protocol
+pub const LOADED_IMAGE_PROTOCOL_GUID: EfiGuid = EfiGuid::new(
+    0x5b1b31a1, 0x9562, 0x11d2,
+    [0x8e, 0x3f, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b]);
+
+/// Describes an image which has been loaded into memory. Installed on the
+/// image handle of every loaded image.
+#[repr(C)]
+pub struct EfiLoadedImageProtocol {
+    /// Defines the revision of the EFI_LOADED_IMAGE_PROTOCOL structure.
+    pub revision: u32,
+
+    /// Parent image's image handle. NULL if the image is loaded directly from
+    /// the firmware's boot manager.
+    pub parent_handle: EfiHandle,
+
+    /// The image's EFI system table pointer.
+    pub system_table: *const EfiSystemTable,
+
+    /// The device handle that the EFI Image was loaded from.
+    pub device_handle: EfiHandle,
+
+    /// A pointer to the file path portion specific to DeviceHandle that the
+    /// EFI Image was loaded from.
+    pub file_path: *const EfiDevicePathProtocol,
+
+    /// Reserved. DO NOT USE.
+    pub reserved: usize,
+
+    /// The size in bytes of LoadOptions.
+    pub load_options_size: u32,
+
+    /// A pointer to the image's binary load options.
+    pub load_options: *const u8,
+
+    /// The base address at which the image was loaded.
+    pub image_base: *const u8,
+
+    /// The size in bytes of the loaded image.
+    pub image_size: u64,
+
+    /// The memory type that the code sections were loaded as.
+    pub image_code_type: u32,
+
+    /// The memory type that the data sections were loaded as.
+    pub image_data_type: u32,
+
+    /// Function that unloads the image.
+    pub _unload: usize,
+}
+
+/// Short name of the Loaded Image Protocol
+pub type LoadedImage = EfiLoadedImageProtocol;
+
+unsafe impl Protocol for EfiLoadedImageProtocol {
+    const GUID: EfiGuid = LOADED_IMAGE_PROTOCOL_GUID;
+}
+
+impl EfiLoadedImageProtocol {
+    /// Get the address the image was loaded at
+    pub fn image_base(&self) -> u64 {
+        self.image_base as u64
+    }
+
+    /// Get the size of the loaded image in bytes
+    pub fn image_size(&self) -> u64 {
+        self.image_size
+    }
+
+    /// Get the memory type the code sections were loaded as
+    pub fn code_type(&self) -> EfiMemoryType {
+        self.image_code_type.into()
+    }
+
+    /// Get the memory type the data sections were loaded as
+    pub fn data_type(&self) -> EfiMemoryType {
+        self.image_data_type.into()
+    }
+
+    /// Get the handle of the device the image was loaded from
+    pub fn device(&self) -> EfiHandle {
+        self.device_handle
+    }
+
+    /// Get the raw load options
+    pub fn load_options(&self) -> &[u8] {
+        if self.load_options.is_null() { return &[]; }
+
+        unsafe {
+            core::slice::from_raw_parts(self.load_options,
+                self.load_options_size as usize)
+        }
+    }
+
+    /// Get the load options as a UCS-2 string, up to the first null
+    /// character
+    ///
+    /// Boot managers and the shell pass the command line this way. Returns
+    /// `None` if the options can't be UCS-2.
+    pub fn load_options_string(&self) -> Option<CString16> {
+        // UCS-2 characters may not be aligned in the raw buffer
+        if self.load_options as usize % 2 != 0 { return None; }
+
+        let chars = if self.load_options.is_null() { &[][..] } else {
+            unsafe {
+                core::slice::from_raw_parts(self.load_options as *const u16,
+                    self.load_options_size as usize / 2)
+            }
+        };
+
+        // Not everyone null terminates the options
+        let len = chars.iter().position(|&chr| chr == 0)
+            .unwrap_or(chars.len());
+
+        let mut string = chars[..len].to_vec();
+        string.push(0);
+
+        CString16::from_vec_with_nul(string).ok()
+    }
+
+    /// Get the load options decoded as a command line
+    pub fn command_line(&self) -> String {
+        self.load_options_string()
+            .map_or_else(String::new, |options| String::from(&*options))
+    }
+}
+
+/// Get the Loaded Image Protocol of the running image
+///
+/// Requires the image handle to be registered with `register_image_handle()`
+pub fn loaded_image() -> EfiResult<&'static LoadedImage> {
+    open::<LoadedImage>(image_handle()?)
+}
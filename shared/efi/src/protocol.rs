@@ -0,0 +1,217 @@
+//! Generic protocol discovery on top of the boot services handle database
+
+use alloc::vec::Vec;
+use crate::{boot_services, free_pool, image_handle, EfiGuid, EfiHandle};
+use crate::{EfiResult, EfiSimpleTextInputProtocol, EfiSimpleTextOutputProtocol};
+
+/// GUID of the Device Path Protocol
+pub const DEVICE_PATH_PROTOCOL_GUID: EfiGuid = EfiGuid::new(
+    0x09576e91, 0x6d3f, 0x11d2,
+    [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b]);
+
+/// GUID of the Simple Text Input Protocol
+pub const SIMPLE_TEXT_INPUT_PROTOCOL_GUID: EfiGuid = EfiGuid::new(
+    0x387477c1, 0x69c7, 0x11d2,
+    [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b]);
+
+/// GUID of the Simple Text Output Protocol
+pub const SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID: EfiGuid = EfiGuid::new(
+    0x387477c2, 0x69c7, 0x11d2,
+    [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b]);
+
+/// `OpenProtocol()` attribute: used by a driver to get a protocol interface
+/// from a handle, without being tracked as a consumer
+pub const OPEN_PROTOCOL_GET_PROTOCOL: u32 = 0x02;
+
+/// `OpenProtocol()` attribute: only test for the presence of the protocol
+pub const OPEN_PROTOCOL_TEST_PROTOCOL: u32 = 0x04;
+
+/// `OpenProtocol()` attribute: open the protocol exclusively
+pub const OPEN_PROTOCOL_EXCLUSIVE: u32 = 0x20;
+
+/// Kind of interface passed to `InstallProtocolInterface()`
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub enum EfiInterfaceType {
+    NativeInterface,
+}
+
+/// Which handles `LocateHandle()` and `LocateHandleBuffer()` return
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub enum EfiLocateSearchType {
+    /// Every handle in the system
+    AllHandles,
+
+    /// Handles newly registered for the `RegisterProtocolNotify()` key
+    ByRegisterNotify,
+
+    /// Handles supporting the given protocol
+    ByProtocol,
+}
+
+/// An agent consuming a protocol, as returned by
+/// `OpenProtocolInformation()`
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct EfiOpenProtocolInformationEntry {
+    pub agent_handle:      EfiHandle,
+    pub controller_handle: EfiHandle,
+    pub attributes:        u32,
+    pub open_count:        u32,
+}
+
+/// Header of a device path node. A device path is a sequence of nodes ending
+/// with an end of hardware device path node.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct EfiDevicePathProtocol {
+    /// Kind of device path node
+    pub typ: u8,
+
+    /// Sub-kind of the device path node
+    pub sub_type: u8,
+
+    /// Length of this node in bytes, including this header
+    pub length: [u8; 2],
+}
+
+/// A protocol interface structure identified by a GUID
+///
+/// # Safety
+///
+/// The implementing type must match the layout of the interface the
+/// firmware installs for `GUID`.
+pub unsafe trait Protocol {
+    /// The GUID identifying the protocol
+    const GUID: EfiGuid;
+}
+
+unsafe impl Protocol for EfiDevicePathProtocol {
+    const GUID: EfiGuid = DEVICE_PATH_PROTOCOL_GUID;
+}
+
+unsafe impl Protocol for EfiSimpleTextInputProtocol {
+    const GUID: EfiGuid = SIMPLE_TEXT_INPUT_PROTOCOL_GUID;
+}
+
+unsafe impl Protocol for EfiSimpleTextOutputProtocol {
+    const GUID: EfiGuid = SIMPLE_TEXT_OUTPUT_PROTOCOL_GUID;
+}
+
+/// Find the first instance of the protocol `P` in the system
+///
+/// Protocol references stay valid until boot services are exited or the
+/// protocol is uninstalled by its driver.
+pub fn locate<P: Protocol>() -> EfiResult<&'static P> {
+    let mut interface = core::ptr::null_mut();
+
+    unsafe {
+        (boot_services()?.locate_protocol)(
+            &P::GUID, core::ptr::null(), &mut interface)
+    }.into_result()?;
+
+    Ok(unsafe { &*(interface as *const P) })
+}
+
+/// Get the protocol `P` installed on `handle`, using the image handle
+/// registered with `register_image_handle()` as the agent
+pub fn open<P: Protocol>(handle: EfiHandle) -> EfiResult<&'static P> {
+    Ok(unsafe { &*open_raw::<P>(handle)? })
+}
+
+/// Get a raw pointer to the protocol `P` installed on `handle`, for the
+/// few protocols whose fields the consumer is expected to modify
+pub fn open_raw<P: Protocol>(handle: EfiHandle) -> EfiResult<*mut P> {
+    let mut interface = core::ptr::null_mut();
+
+    unsafe {
+        (boot_services()?.open_protocol)(
+            handle,
+            &P::GUID,
+            &mut interface,
+            image_handle()?,
+            EfiHandle::NULL,
+            OPEN_PROTOCOL_GET_PROTOCOL
+        )
+    }.into_result()?;
+
+    Ok(interface as *mut P)
+}
+
+/// Get the protocol `P` installed on `handle` with `HandleProtocol()`
+///
+/// Prefer `open()`, this is for when no image handle is available
+pub fn handle_protocol<P: Protocol>(handle: EfiHandle)
+        -> EfiResult<&'static P> {
+    let mut interface = core::ptr::null_mut();
+
+    unsafe {
+        (boot_services()?.handle_protocol)(handle, &P::GUID, &mut interface)
+    }.into_result()?;
+
+    Ok(unsafe { &*(interface as *const P) })
+}
+
+/// Returns `true` if the protocol `P` is installed on `handle`
+pub fn supports<P: Protocol>(handle: EfiHandle) -> bool {
+    let supported = || -> EfiResult<()> {
+        unsafe {
+            (boot_services()?.open_protocol)(
+                handle,
+                &P::GUID,
+                &mut core::ptr::null_mut(),
+                image_handle()?,
+                EfiHandle::NULL,
+                OPEN_PROTOCOL_TEST_PROTOCOL
+            )
+        }.into_result()
+    };
+
+    supported().is_ok()
+}
+
+/// Get all handles the protocol `P` is installed on
+pub fn locate_handles<P: Protocol>() -> EfiResult<Vec<EfiHandle>> {
+    let mut count = 0;
+    let mut buffer = core::ptr::null_mut();
+
+    unsafe {
+        (boot_services()?.locate_handle_buffer)(
+            EfiLocateSearchType::ByProtocol,
+            &P::GUID,
+            core::ptr::null(),
+            &mut count,
+            &mut buffer
+        )
+    }.into_result()?;
+
+    // Copy the handles out of the firmware allocated buffer
+    let handles = unsafe {
+        core::slice::from_raw_parts(buffer, count)
+    }.to_vec();
+
+    let _ = unsafe { free_pool(buffer as *mut u8) };
+
+    Ok(handles)
+}
+
+/// Get the GUIDs of all protocols installed on `handle`
+pub fn protocols_per_handle(handle: EfiHandle) -> EfiResult<Vec<EfiGuid>> {
+    let mut count = 0;
+    let mut buffer = core::ptr::null_mut();
+
+    unsafe {
+        (boot_services()?.protocols_per_handle)(handle, &mut buffer, &mut count)
+    }.into_result()?;
+
+    // Copy the GUIDs out of the firmware allocated buffer
+    let guids = unsafe { core::slice::from_raw_parts(buffer, count) }
+        .iter()
+        .map(|&guid| unsafe { *guid })
+        .collect();
+
+    let _ = unsafe { free_pool(buffer as *mut u8) };
+
+    Ok(guids)
+}
@@ -0,0 +1,394 @@
+//! PXE Base Code Protocol: downloading files over TFTP from the server the
+//! image was network booted from
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::{loaded_image, open, supports, EfiError, EfiGuid};
+use crate::{EfiHandle, EfiResult, EfiStatus, Protocol};
+
+/// GUID of the PXE Base Code Protocol
+pub const PXE_BASE_CODE_PROTOCOL_GUID: EfiGuid = EfiGuid::new(
+    0x03c4e603, 0xac28, 0x11d3,
+    [0x9a, 0x2d, 0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d]);
+
+/// An IPv4 or IPv6 address. IPv4 addresses use the first 4 bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C, align(4))]
+pub struct EfiIpAddress(pub [u8; 16]);
+
+impl EfiIpAddress {
+    /// Create an IPv4 address
+    pub const fn ipv4(addr: [u8; 4]) -> Self {
+        EfiIpAddress([addr[0], addr[1], addr[2], addr[3],
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0])
+    }
+}
+
+/// Operations of `Mtftp()`
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub enum EfiPxeBaseCodeTftpOpcode {
+    TftpFirst,
+    TftpGetFileSize,
+    TftpReadFile,
+    TftpWriteFile,
+    TftpReadDirectory,
+    MtftpGetFileSize,
+    MtftpReadFile,
+    MtftpReadDirectory,
+    MtftpLast,
+}
+
+/// A DHCPv4 packet as seen by the PXE Base Code Protocol
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct EfiPxeBaseCodeDhcpv4Packet {
+    pub bootp_opcode:      u8,
+    pub bootp_hw_type:     u8,
+    pub bootp_hw_addr_len: u8,
+    pub bootp_gate_hops:   u8,
+    pub bootp_ident:       u32,
+    pub bootp_seconds:     u16,
+    pub bootp_flags:       u16,
+    pub bootp_ci_addr:     [u8; 4],
+    pub bootp_yi_addr:     [u8; 4],
+    pub bootp_si_addr:     [u8; 4],
+    pub bootp_gi_addr:     [u8; 4],
+    pub bootp_hw_addr:     [u8; 16],
+    pub bootp_srv_name:    [u8; 64],
+    pub bootp_boot_file:   [u8; 128],
+    pub dhcp_magic:        u32,
+    pub dhcp_options:      [u8; 56],
+}
+
+/// A raw DHCP packet received by the PXE Base Code Protocol
+#[derive(Clone, Copy)]
+#[repr(C, align(4))]
+pub struct EfiPxeBaseCodePacket {
+    pub raw: [u8; 1472],
+}
+
+impl EfiPxeBaseCodePacket {
+    /// View the packet as a DHCPv4 packet
+    pub fn dhcpv4(&self) -> &EfiPxeBaseCodeDhcpv4Packet {
+        unsafe { &*(self.raw.as_ptr() as *const EfiPxeBaseCodeDhcpv4Packet) }
+    }
+}
+
+/// State of the PXE Base Code Protocol
+///
+/// Only the leading fields are bound, the IP filter, ARP cache, route table
+/// and error packets which follow are never accessed.
+#[repr(C)]
+pub struct EfiPxeBaseCodeMode {
+    pub started:                bool,
+    pub ipv6_available:         bool,
+    pub ipv6_supported:         bool,
+    pub using_ipv6:             bool,
+    pub bis_supported:          bool,
+    pub bis_detected:           bool,
+    pub auto_arp:               bool,
+    pub send_guid:              bool,
+    pub dhcp_discover_valid:    bool,
+    pub dhcp_ack_received:      bool,
+    pub proxy_offer_received:   bool,
+    pub pxe_discover_valid:     bool,
+    pub pxe_reply_received:     bool,
+    pub pxe_bis_reply_received: bool,
+    pub icmp_error_received:    bool,
+    pub tftp_error_received:    bool,
+    pub make_callbacks:         bool,
+    pub ttl:                    u8,
+    pub tos:                    u8,
+    pub station_ip:             EfiIpAddress,
+    pub subnet_mask:            EfiIpAddress,
+    pub dhcp_discover:          EfiPxeBaseCodePacket,
+    pub dhcp_ack:               EfiPxeBaseCodePacket,
+    pub proxy_offer:            EfiPxeBaseCodePacket,
+    pub pxe_discover:           EfiPxeBaseCodePacket,
+    pub pxe_reply:              EfiPxeBaseCodePacket,
+    pub pxe_bis_reply:          EfiPxeBaseCodePacket,
+}
+
+/// Used to control PXE-compatible devices, the protocol the firmware uses to
+/// network boot
+#[repr(C)]
+pub struct EfiPxeBaseCodeProtocol {
+    /// The revision of the EFI_PXE_BASE_CODE_PROTOCOL.
+    pub revision: u64,
+
+    /// Enables the use of the PXE Base Code Protocol functions.
+    pub start: unsafe extern "efiapi" fn(
+        this:     *const EfiPxeBaseCodeProtocol,
+        use_ipv6: bool,
+    ) -> EfiStatus,
+
+    /// Disables the use of the PXE Base Code Protocol functions.
+    pub stop: unsafe extern "efiapi" fn(
+        this: *const EfiPxeBaseCodeProtocol,
+    ) -> EfiStatus,
+
+    /// Attempts to complete a DHCPv4 D.O.R.A. (discover / offer / request /
+    /// acknowledge) or DHCPv6 S.A.R.R (solicit / advertise / request / reply)
+    /// sequence.
+    pub dhcp: unsafe extern "efiapi" fn(
+        this:        *const EfiPxeBaseCodeProtocol,
+        sort_offers: bool,
+    ) -> EfiStatus,
+
+    /// Attempts to complete the PXE Boot Server and/or boot image discovery
+    /// sequence.
+    pub _discover: usize,
+
+    /// Used to perform TFTP and MTFTP services.
+    pub mtftp: unsafe extern "efiapi" fn(
+        this:            *const EfiPxeBaseCodeProtocol,
+        operation:       EfiPxeBaseCodeTftpOpcode,
+        buffer:          *mut u8,
+        overwrite:       bool,
+        buffer_size:     &mut u64,
+        block_size:      *const usize,
+        server_ip:       *const EfiIpAddress,
+        filename:        *const u8,
+        info:            *const u8,
+        dont_use_buffer: bool,
+    ) -> EfiStatus,
+
+    /// Writes a UDP packet to the network interface.
+    pub _udp_write: usize,
+
+    /// Reads a UDP packet from the network interface.
+    pub _udp_read: usize,
+
+    /// Updates the IP receive filters of the network device.
+    pub _set_ip_filter: usize,
+
+    /// Uses the ARP protocol to resolve a MAC address.
+    pub _arp: usize,
+
+    /// Updates the parameters that affect the operation of the PXE Base Code
+    /// Protocol.
+    pub _set_parameters: usize,
+
+    /// Updates the station IP address and/or subnet mask values.
+    pub _set_station_ip: usize,
+
+    /// Updates the contents of the cached DHCP and Discover packets.
+    pub _set_packets: usize,
+
+    /// Pointer to the EFI_PXE_BASE_CODE_MODE data for this device.
+    pub mode: *const EfiPxeBaseCodeMode,
+}
+
+/// Short name of the PXE Base Code Protocol
+pub type PxeBaseCode = EfiPxeBaseCodeProtocol;
+
+unsafe impl Protocol for EfiPxeBaseCodeProtocol {
+    const GUID: EfiGuid = PXE_BASE_CODE_PROTOCOL_GUID;
+}
+
+/// Initial size of the buffer directory listings are read into
+const DIRECTORY_BUFFER_SIZE: usize = 16 * 1024;
+
+/// Largest directory listing we are willing to read
+const MAX_DIRECTORY_SIZE: usize = 1024 * 1024;
+
+/// Encode `name` as a null terminated ASCII file name
+fn encode_filename(name: &str) -> EfiResult<Vec<u8>> {
+    if !name.is_ascii() || name.contains('\0') {
+        return Err(EfiError(EfiStatus::INVALID_PARAMETER));
+    }
+
+    let mut encoded = Vec::with_capacity(name.len() + 1);
+    encoded.extend_from_slice(name.as_bytes());
+    encoded.push(0);
+
+    Ok(encoded)
+}
+
+/// A TFTP client on top of the PXE Base Code Protocol
+#[derive(Clone, Copy)]
+pub struct Tftp {
+    /// Handle of the network interface to use
+    device: EfiHandle,
+
+    /// The PXE Base Code Protocol of the network interface
+    pxe: &'static PxeBaseCode,
+
+    /// Address of the TFTP server
+    server: EfiIpAddress,
+}
+
+impl Tftp {
+    /// Connect to the TFTP server the running image was network booted from
+    ///
+    /// Fails with `EfiStatus::NOT_FOUND` if the image wasn't network booted
+    /// or DHCP didn't provide a server. Other network interfaces are left
+    /// alone, configuring them could stall a disk boot on DHCP timeouts.
+    /// The protocol is started and DHCP is run if the firmware didn't do so
+    /// already, callers needing the server repeatedly should keep the
+    /// returned `Tftp` around.
+    pub fn boot_server() -> EfiResult<Tftp> {
+        let device = loaded_image()?.device();
+
+        if !supports::<PxeBaseCode>(device) {
+            return Err(EfiError(EfiStatus::NOT_FOUND));
+        }
+
+        let pxe = open::<PxeBaseCode>(device)?;
+        let mode = unsafe { &*pxe.mode };
+
+        if !mode.started {
+            unsafe { (pxe.start)(pxe, false) }.into_result()?;
+        }
+
+        if !mode.dhcp_ack_received {
+            unsafe { (pxe.dhcp)(pxe, true) }.into_result()?;
+        }
+
+        // A proxy DHCP server takes precedence over the regular one when it
+        // comes to boot parameters
+        let packet = if mode.proxy_offer_received {
+            mode.proxy_offer.dhcpv4()
+        } else {
+            mode.dhcp_ack.dhcpv4()
+        };
+
+        if packet.bootp_si_addr == [0; 4] {
+            return Err(EfiError(EfiStatus::NOT_FOUND));
+        }
+
+        Ok(Tftp {
+            device,
+            pxe,
+            server: EfiIpAddress::ipv4(packet.bootp_si_addr),
+        })
+    }
+
+    /// Connect to the TFTP server at `server` through the network interface
+    /// `device`, whose PXE Base Code Protocol must have been started already
+    pub fn new(device: EfiHandle, server: EfiIpAddress) -> EfiResult<Tftp> {
+        Ok(Tftp { device, pxe: open::<PxeBaseCode>(device)?, server })
+    }
+
+    /// Get the handle of the network interface used
+    pub fn device(&self) -> EfiHandle {
+        self.device
+    }
+
+    /// Get the address of the TFTP server
+    pub fn server(&self) -> EfiIpAddress {
+        self.server
+    }
+
+    /// Get the size of the file `name` on the server in bytes
+    pub fn file_size(&self, name: &str) -> EfiResult<u64> {
+        let name = encode_filename(name)?;
+        let mut size = 0;
+
+        unsafe {
+            (self.pxe.mtftp)(
+                self.pxe,
+                EfiPxeBaseCodeTftpOpcode::TftpGetFileSize,
+                core::ptr::null_mut(),
+                false,
+                &mut size,
+                core::ptr::null(),
+                &self.server,
+                name.as_ptr(),
+                core::ptr::null(),
+                false
+            )
+        }.into_result()?;
+
+        Ok(size)
+    }
+
+    /// Download the whole file `name` from the server
+    pub fn read_file(&self, name: &str) -> EfiResult<Vec<u8>> {
+        let size = self.file_size(name)?;
+
+        let name = encode_filename(name)?;
+
+        // The size is whatever the server claims, don't let it abort
+        let mut data = Vec::new();
+        data.try_reserve_exact(size as usize)
+            .map_err(|_| EfiError(EfiStatus::OUT_OF_RESOURCES))?;
+        data.resize(size as usize, 0);
+
+        let mut read = size;
+
+        unsafe {
+            (self.pxe.mtftp)(
+                self.pxe,
+                EfiPxeBaseCodeTftpOpcode::TftpReadFile,
+                data.as_mut_ptr(),
+                false,
+                &mut read,
+                core::ptr::null(),
+                &self.server,
+                name.as_ptr(),
+                core::ptr::null(),
+                false
+            )
+        }.into_result()?;
+
+        data.truncate(read as usize);
+        Ok(data)
+    }
+
+    /// Get the names of the files in the directory `name` on the server
+    ///
+    /// Many TFTP servers don't support directory listings, in which case
+    /// this fails with `EfiStatus::TFTP_ERROR`
+    pub fn read_dir(&self, name: &str) -> EfiResult<Vec<String>> {
+        let name = encode_filename(name)?;
+        let mut buffer = alloc::vec![0u8; DIRECTORY_BUFFER_SIZE];
+
+        // The size of the listing is not known up front, grow the buffer
+        // until it fits
+        let size = loop {
+            let mut size = buffer.len() as u64;
+
+            let ret = unsafe {
+                (self.pxe.mtftp)(
+                    self.pxe,
+                    EfiPxeBaseCodeTftpOpcode::TftpReadDirectory,
+                    buffer.as_mut_ptr(),
+                    false,
+                    &mut size,
+                    core::ptr::null(),
+                    &self.server,
+                    name.as_ptr(),
+                    core::ptr::null(),
+                    false
+                )
+            };
+
+            if ret == EfiStatus::BUFFER_TOO_SMALL &&
+                    buffer.len() < MAX_DIRECTORY_SIZE {
+                buffer.resize(buffer.len() * 2, 0);
+                continue;
+            }
+
+            ret.into_result()?;
+            break size as usize;
+        };
+
+        // Entries are a null terminated file name followed by a null
+        // terminated description, an empty name ends the listing
+        let names = buffer[..size.min(buffer.len())]
+            .split(|&byte| byte == 0)
+            .step_by(2)
+            .take_while(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect();
+
+        Ok(names)
+    }
+}
+
+/// Download the file `name` from the TFTP server the image was booted from
+pub fn tftp_read_file(name: &str) -> EfiResult<Vec<u8>> {
+    Tftp::boot_server()?.read_file(name)
+}
@@ -0,0 +1,535 @@
+//! EFI Runtime Services: time, variables and system reset
+//!
+//! Unlike boot services, runtime services stay available after
+//! `exit_boot_services()`, thus the runtime services table is tracked
+//! separately from the system table.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use crate::{EfiError, EfiGuid, EfiMemoryDescriptor, EfiResult, EfiStatus};
+use crate::{CStr16, EfiTableHeader, MemoryMap};
+
+/// GUID of the EFI global variables such as `BootOrder` or `Boot####`
+pub const EFI_GLOBAL_VARIABLE_GUID: EfiGuid = EfiGuid::new(
+    0x8be4df61, 0x93ca, 0x11d2,
+    [0xaa, 0x0d, 0x00, 0xe0, 0x98, 0x03, 0x2b, 0x8c]);
+
+/// The variable is stored in NVRAM and survives a reset
+pub const VARIABLE_NON_VOLATILE: u32 = 0x01;
+
+/// The variable is accessible while boot services are available
+pub const VARIABLE_BOOTSERVICE_ACCESS: u32 = 0x02;
+
+/// The variable is accessible after boot services have been exited
+pub const VARIABLE_RUNTIME_ACCESS: u32 = 0x04;
+
+/// The variable is a hardware error record
+pub const VARIABLE_HARDWARE_ERROR_RECORD: u32 = 0x08;
+
+/// The variable requires time based authenticated writes
+pub const VARIABLE_TIME_BASED_AUTHENTICATED_WRITE_ACCESS: u32 = 0x20;
+
+/// Data written to the variable is appended to the existing data
+pub const VARIABLE_APPEND_WRITE: u32 = 0x40;
+
+/// `ConvertPointer()` disposition allowing a null pointer to be converted
+pub const OPTIONAL_POINTER: usize = 0x1;
+
+/// Number of UCS-2 characters, including the null terminator, of variable
+/// names stored without allocating. Longer names are moved to the heap.
+const INLINE_VARIABLE_NAME: usize = 256;
+
+/// A pointer to the EFI runtime services table saved when the system table is
+/// registered
+pub(crate) static EFI_RUNTIME_SERVICES: AtomicPtr<EfiRuntimeServices> =
+    AtomicPtr::new(core::ptr::null_mut());
+
+/// A point in time as reported by the real time clock
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct EfiTime {
+    /// 1900 - 9999
+    pub year: u16,
+
+    /// 1 - 12
+    pub month: u8,
+
+    /// 1 - 31
+    pub day: u8,
+
+    /// 0 - 23
+    pub hour: u8,
+
+    /// 0 - 59
+    pub minute: u8,
+
+    /// 0 - 59
+    pub second: u8,
+
+    pub pad1: u8,
+
+    /// 0 - 999,999,999
+    pub nanosecond: u32,
+
+    /// Offset from UTC in minutes, -1440 to 1440, or 2047 if the time is
+    /// local time
+    pub time_zone: i16,
+
+    /// Daylight saving time information
+    pub daylight: u8,
+
+    pub pad2: u8,
+}
+
+impl fmt::Display for EfiTime {
+    /// Display the time as `YYYY-MM-DD hh:mm:ss`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day,
+            self.hour, self.minute, self.second)
+    }
+}
+
+/// Capabilities of the real time clock
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct EfiTimeCapabilities {
+    /// Reporting resolution of the clock in counts per second
+    pub resolution: u32,
+
+    /// Timekeeping accuracy in parts per million times 1,000,000
+    pub accuracy: u32,
+
+    /// Whether a time set operation clears the time below the resolution
+    pub sets_to_zero: bool,
+}
+
+/// The type of reset to perform with `ResetSystem()`
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub enum EfiResetType {
+    /// Reset every circuit in the system
+    Cold,
+
+    /// Reset the processors, leaving the rest of the system alone
+    Warm,
+
+    /// Power the system off
+    Shutdown,
+
+    /// A platform specific reset described by the reset data
+    PlatformSpecific,
+}
+
+/// Contains a table header and pointers to all of the runtime services.
+#[repr(C)]
+pub struct EfiRuntimeServices {
+    /// The table header for the EFI Runtime Services Table. This header
+    /// contains the EFI_RUNTIME_SERVICES_SIGNATURE and
+    /// EFI_RUNTIME_SERVICES_REVISION values along with the size of the
+    /// EFI_RUNTIME_SERVICES structure and a 32-bit CRC to verify that the
+    /// contents of the EFI Runtime Services Table are valid.
+    pub header: EfiTableHeader,
+
+    /// Returns the current time and date, and the time-keeping capabilities
+    /// of the platform.
+    pub get_time: unsafe extern "efiapi" fn(
+        time:         *mut EfiTime,
+        capabilities: *mut EfiTimeCapabilities,
+    ) -> EfiStatus,
+
+    /// Sets the current local time and date information.
+    pub set_time: unsafe extern "efiapi" fn(
+        time: *const EfiTime,
+    ) -> EfiStatus,
+
+    /// Returns the current wakeup alarm clock setting.
+    pub _get_wakeup_time: usize,
+
+    /// Sets the system wakeup alarm clock time.
+    pub _set_wakeup_time: usize,
+
+    /// Used by a UEFI OS loader to convert from physical addressing to
+    /// virtual addressing.
+    pub set_virtual_address_map: unsafe extern "efiapi" fn(
+        memory_map_size:    usize,
+        descriptor_size:    usize,
+        descriptor_version: u32,
+        virtual_map:        *mut EfiMemoryDescriptor,
+    ) -> EfiStatus,
+
+    /// Used by EFI components to convert internal pointers when switching to
+    /// virtual addressing.
+    pub convert_pointer: unsafe extern "efiapi" fn(
+        debug_disposition: usize,
+        address:           *mut *mut u8,
+    ) -> EfiStatus,
+
+    /// Returns the value of a variable.
+    pub get_variable: unsafe extern "efiapi" fn(
+        variable_name: *const u16,
+        vendor_guid:   *const EfiGuid,
+        attributes:    *mut u32,
+        data_size:     &mut usize,
+        data:          *mut u8,
+    ) -> EfiStatus,
+
+    /// Enumerates the current variable names.
+    pub get_next_variable_name: unsafe extern "efiapi" fn(
+        variable_name_size: &mut usize,
+        variable_name:      *mut u16,
+        vendor_guid:        &mut EfiGuid,
+    ) -> EfiStatus,
+
+    /// Sets the value of a variable.
+    pub set_variable: unsafe extern "efiapi" fn(
+        variable_name: *const u16,
+        vendor_guid:   *const EfiGuid,
+        attributes:    u32,
+        data_size:     usize,
+        data:          *const u8,
+    ) -> EfiStatus,
+
+    /// Returns the next high 32 bits of the platform's monotonic counter.
+    pub _get_next_high_monotonic_count: usize,
+
+    /// Resets the entire platform.
+    pub reset_system: unsafe extern "efiapi" fn(
+        reset_type:   EfiResetType,
+        reset_status: EfiStatus,
+        data_size:    usize,
+        reset_data:   *const u8,
+    ) -> !,
+
+    /// Passes capsules to the firmware with both virtual and physical
+    /// mappings.
+    pub _update_capsule: usize,
+
+    /// Returns if the capsule can be supported via UpdateCapsule().
+    pub _query_capsule_capabilities: usize,
+
+    /// Returns information about the EFI variables.
+    pub _query_variable_info: usize,
+}
+
+/// Get the registered runtime services table
+///
+/// Fails with `EfiStatus::UNSUPPORTED` if no system table has been registered
+fn runtime_services() -> EfiResult<&'static EfiRuntimeServices> {
+    let rt = EFI_RUNTIME_SERVICES.load(Ordering::SeqCst);
+
+    // We can't do anything if it is null
+    if rt.is_null() { return Err(EfiError(EfiStatus::UNSUPPORTED)); }
+
+    Ok(unsafe { &*rt })
+}
+
+/// Get the current time from the real time clock
+pub fn get_time() -> EfiResult<EfiTime> {
+    let mut time = EfiTime::default();
+
+    unsafe {
+        (runtime_services()?.get_time)(&mut time, core::ptr::null_mut())
+    }.into_result()?;
+
+    Ok(time)
+}
+
+/// Get the capabilities of the real time clock
+pub fn time_capabilities() -> EfiResult<EfiTimeCapabilities> {
+    let mut time = EfiTime::default();
+    let mut capabilities = EfiTimeCapabilities::default();
+
+    unsafe {
+        (runtime_services()?.get_time)(&mut time, &mut capabilities)
+    }.into_result()?;
+
+    Ok(capabilities)
+}
+
+/// Set the real time clock to `time`
+pub fn set_time(time: &EfiTime) -> EfiResult<()> {
+    unsafe { (runtime_services()?.set_time)(time) }.into_result()
+}
+
+/// Information about a variable read with `get_variable()`
+#[derive(Clone, Copy, Debug)]
+pub struct VariableInfo {
+    /// Attributes of the variable
+    pub attributes: u32,
+
+    /// Size of the variable data in bytes
+    pub size: usize,
+}
+
+/// Read the variable `name` from the namespace `vendor` into `data`
+///
+/// If `data` is too small, fails with `EfiStatus::BUFFER_TOO_SMALL`. Use
+/// `get_variable_size()` to find out how big the variable is. Use `cstr16!`
+/// for names known at compile time.
+pub fn get_variable(name: &CStr16, vendor: &EfiGuid, data: &mut [u8])
+        -> EfiResult<VariableInfo> {
+    let mut info = VariableInfo {
+        attributes: 0,
+        size:       data.len(),
+    };
+
+    unsafe {
+        (runtime_services()?.get_variable)(
+            name.as_ptr(),
+            vendor,
+            &mut info.attributes,
+            &mut info.size,
+            data.as_mut_ptr()
+        )
+    }.into_result()?;
+
+    Ok(info)
+}
+
+/// Get the size in bytes of the data of the variable `name` from the
+/// namespace `vendor`
+pub fn get_variable_size(name: &CStr16, vendor: &EfiGuid)
+        -> EfiResult<usize> {
+    let mut size = 0;
+
+    let ret = unsafe {
+        (runtime_services()?.get_variable)(
+            name.as_ptr(),
+            vendor,
+            core::ptr::null_mut(),
+            &mut size,
+            core::ptr::null_mut()
+        )
+    };
+
+    // Asking with an empty buffer makes the firmware report the size
+    if ret == EfiStatus::BUFFER_TOO_SMALL || ret.into_result().is_ok() {
+        Ok(size)
+    } else {
+        Err(EfiError(ret))
+    }
+}
+
+/// Create, update or delete the variable `name` in the namespace `vendor`
+///
+/// Writing empty `data` without `VARIABLE_APPEND_WRITE` deletes the variable
+pub fn set_variable(name: &CStr16, vendor: &EfiGuid, attributes: u32,
+        data: &[u8]) -> EfiResult<()> {
+    unsafe {
+        (runtime_services()?.set_variable)(
+            name.as_ptr(),
+            vendor,
+            attributes,
+            data.len(),
+            data.as_ptr()
+        )
+    }.into_result()
+}
+
+/// The name and namespace of a variable as returned by `variable_names()`
+#[derive(Clone)]
+pub struct VariableName {
+    /// Null terminated UCS-2 name of the variable, unless it is too long
+    inline: [u16; INLINE_VARIABLE_NAME],
+
+    /// Null terminated UCS-2 name of the variable if it didn't fit `inline`,
+    /// empty otherwise
+    heap: Vec<u16>,
+
+    /// The namespace of the variable
+    vendor: EfiGuid,
+}
+
+impl VariableName {
+    /// Get the buffer holding the name
+    fn buffer(&mut self) -> &mut [u16] {
+        if self.heap.is_empty() { &mut self.inline } else { &mut self.heap }
+    }
+
+    /// Move the name into a buffer of `len` characters on the heap
+    ///
+    /// Fails with `EfiStatus::OUT_OF_RESOURCES` once boot services are gone
+    /// and nothing can be allocated anymore.
+    fn grow(&mut self, len: usize) -> EfiResult<()> {
+        let mut heap = Vec::new();
+        heap.try_reserve_exact(len)
+            .map_err(|_| EfiError(EfiStatus::OUT_OF_RESOURCES))?;
+
+        heap.extend_from_slice(self.buffer());
+        heap.resize(len, 0);
+
+        self.heap = heap;
+        Ok(())
+    }
+
+    /// Get the namespace of the variable
+    pub fn vendor(&self) -> EfiGuid {
+        self.vendor
+    }
+
+    /// Get the name of the variable
+    pub fn name(&self) -> &CStr16 {
+        let name = if self.heap.is_empty() { &self.inline[..] } else {
+            &self.heap[..]
+        };
+
+        // The firmware always null terminates the name
+        CStr16::from_u16_until_nul(name).unwrap_or(crate::cstr16!(""))
+    }
+
+    /// Returns `true` if this is the variable `name` in namespace `vendor`
+    pub fn matches(&self, name: &CStr16, vendor: &EfiGuid) -> bool {
+        self.vendor == *vendor && self.name() == name
+    }
+}
+
+impl fmt::Display for VariableName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Debug for VariableName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}:{}", self.vendor, self)
+    }
+}
+
+/// An iterator over the names of all variables, see `variable_names()`
+pub struct VariableNames {
+    /// The last name returned, which is where the enumeration resumes
+    current: VariableName,
+
+    /// Set once the enumeration is over or failed
+    done: bool,
+}
+
+impl Iterator for VariableNames {
+    type Item = EfiResult<VariableName>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done { return None; }
+
+        let rt = match runtime_services() {
+            Ok(rt) => rt,
+            Err(err) => {
+                self.done = true;
+                return Some(Err(err));
+            }
+        };
+
+        let ret = loop {
+            let buffer = self.current.buffer();
+            let len = buffer.len();
+            let mut size = core::mem::size_of_val(buffer);
+
+            let ret = unsafe {
+                (rt.get_next_variable_name)(
+                    &mut size,
+                    buffer.as_mut_ptr(),
+                    &mut self.current.vendor
+                )
+            };
+
+            // `size` now holds the size the name needs, resume from the
+            // previous name in a buffer big enough for it
+            let needed = (size + 1) / 2;
+            if ret != EfiStatus::BUFFER_TOO_SMALL || needed <= len {
+                break ret;
+            }
+
+            if let Err(err) = self.current.grow(needed) {
+                self.done = true;
+                return Some(Err(err));
+            }
+        };
+
+        // `NOT_FOUND` marks the end of the enumeration
+        if ret == EfiStatus::NOT_FOUND {
+            self.done = true;
+            return None;
+        }
+
+        if let Err(err) = ret.into_result() {
+            self.done = true;
+            return Some(Err(err));
+        }
+
+        Some(Ok(self.current.clone()))
+    }
+}
+
+/// Get an iterator over the names of all variables
+pub fn variable_names() -> VariableNames {
+    VariableNames {
+        current: VariableName {
+            inline: [0u16; INLINE_VARIABLE_NAME],
+            heap:   Vec::new(),
+            vendor: EfiGuid::new(0, 0, 0, [0; 8]),
+        },
+        done: false,
+    }
+}
+
+/// Reset or power off the system
+///
+/// This only returns if runtime services are not available
+pub fn reset_system(typ: EfiResetType, status: EfiStatus) {
+    if let Ok(rt) = runtime_services() {
+        unsafe {
+            (rt.reset_system)(typ, status, 0, core::ptr::null());
+        }
+    }
+}
+
+/// Switch runtime services to the virtual addresses assigned in `map`
+///
+/// Assign the addresses with `MemoryMap::layout_runtime_regions()` first.
+/// This can only be called once, after `exit_boot_services()`, and while the
+/// firmware still runs with its physical mappings. On success the runtime
+/// service wrappers switch to the virtual runtime services table, thus the
+/// kernel must have mapped all runtime regions before calling them again.
+/// The saved configuration table array moves along, the tables it points to
+/// keep their physical addresses.
+///
+/// # Safety
+///
+/// The caller must make sure `map` is the final memory map and the virtual
+/// addresses in it are mapped before any further runtime service call.
+pub unsafe fn set_virtual_address_map(map: &mut MemoryMap) -> EfiResult<()> {
+    let rt = runtime_services()?;
+
+    // Find where the runtime services table ends up before the firmware
+    // starts running from its new location
+    let virt_rt = map.runtime_virtual_address(rt as *const _ as u64)
+        .ok_or(EfiError(EfiStatus::NO_MAPPING))?;
+
+    (rt.set_virtual_address_map)(
+        map.size,
+        map.descriptor_size,
+        map.descriptor_version,
+        map.buffer as *mut EfiMemoryDescriptor
+    ).into_result()?;
+
+    EFI_RUNTIME_SERVICES.store(virt_rt as *mut EfiRuntimeServices,
+        Ordering::SeqCst);
+    crate::config_table::relocate_configuration_tables(map);
+
+    Ok(())
+}
+
+/// Convert `address` from a physical to a virtual address
+///
+/// This is only valid while `SetVirtualAddressMap()` is in progress, which is
+/// when firmware components fix up their internal pointers. Null pointers
+/// are only accepted with the `OPTIONAL_POINTER` disposition.
+///
+/// # Safety
+///
+/// The caller must respect the timing restriction above.
+pub unsafe fn convert_pointer(disposition: usize, address: &mut *mut u8)
+        -> EfiResult<()> {
+    (runtime_services()?.convert_pointer)(disposition, address).into_result()
+}
@@ -0,0 +1,199 @@
+//! EFI status codes as returned by every firmware call
+
+use core::fmt;
+
+/// Bit set in all error status codes
+const ERROR_BIT: usize = 1 << (usize::BITS - 1);
+
+/// A status code returned by the firmware
+///
+/// This is a transparent wrapper around the raw `EFI_STATUS` value, thus it
+/// is safe to use at FFI boundaries even for codes we don't know about.
+/// Codes with the high bit set are errors, other non-zero codes are warnings.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct EfiStatus(pub usize);
+
+/// Result of a firmware call
+pub type EfiResult<T> = core::result::Result<T, EfiError>;
+
+/// Declare the status code constants along with their names
+macro_rules! status_codes {
+    ($($name:ident = $val:expr,)*) => {
+        impl EfiStatus {
+            $(
+                pub const $name: EfiStatus = EfiStatus($val);
+            )*
+
+            /// Get the name of this status code, if it is a known one
+            pub fn name(&self) -> Option<&'static str> {
+                match *self {
+                    $(
+                        EfiStatus::$name => Some(stringify!($name)),
+                    )*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+status_codes! {
+    SUCCESS                = 0,
+
+    LOAD_ERROR             = ERROR_BIT | 0x01,
+    INVALID_PARAMETER      = ERROR_BIT | 0x02,
+    UNSUPPORTED            = ERROR_BIT | 0x03,
+    BAD_BUFFER_SIZE        = ERROR_BIT | 0x04,
+    BUFFER_TOO_SMALL       = ERROR_BIT | 0x05,
+    NOT_READY              = ERROR_BIT | 0x06,
+    DEVICE_ERROR           = ERROR_BIT | 0x07,
+    WRITE_PROTECTED        = ERROR_BIT | 0x08,
+    OUT_OF_RESOURCES       = ERROR_BIT | 0x09,
+    VOLUME_CORRUPTED       = ERROR_BIT | 0x0a,
+    VOLUME_FULL            = ERROR_BIT | 0x0b,
+    NO_MEDIA               = ERROR_BIT | 0x0c,
+    MEDIA_CHANGED          = ERROR_BIT | 0x0d,
+    NOT_FOUND              = ERROR_BIT | 0x0e,
+    ACCESS_DENIED          = ERROR_BIT | 0x0f,
+    NO_RESPONSE            = ERROR_BIT | 0x10,
+    NO_MAPPING             = ERROR_BIT | 0x11,
+    TIMEOUT                = ERROR_BIT | 0x12,
+    NOT_STARTED            = ERROR_BIT | 0x13,
+    ALREADY_STARTED        = ERROR_BIT | 0x14,
+    ABORTED                = ERROR_BIT | 0x15,
+    ICMP_ERROR             = ERROR_BIT | 0x16,
+    TFTP_ERROR             = ERROR_BIT | 0x17,
+    PROTOCOL_ERROR         = ERROR_BIT | 0x18,
+    INCOMPATIBLE_VERSION   = ERROR_BIT | 0x19,
+    SECURITY_VIOLATION     = ERROR_BIT | 0x1a,
+    CRC_ERROR              = ERROR_BIT | 0x1b,
+    END_OF_MEDIA           = ERROR_BIT | 0x1c,
+    END_OF_FILE            = ERROR_BIT | 0x1f,
+    INVALID_LANGUAGE       = ERROR_BIT | 0x20,
+    COMPROMISED_DATA       = ERROR_BIT | 0x21,
+    IP_ADDRESS_CONFLICT    = ERROR_BIT | 0x22,
+    HTTP_ERROR             = ERROR_BIT | 0x23,
+    NETWORK_UNREACHABLE    = ERROR_BIT | 0x64,
+    HOST_UNREACHABLE       = ERROR_BIT | 0x65,
+    PROTOCOL_UNREACHABLE   = ERROR_BIT | 0x66,
+    PORT_UNREACHABLE       = ERROR_BIT | 0x67,
+    CONNECTION_FIN         = ERROR_BIT | 0x68,
+    CONNECTION_RESET       = ERROR_BIT | 0x69,
+    CONNECTION_REFUSED     = ERROR_BIT | 0x6a,
+
+    WARN_UNKNOWN_GLYPH     = 0x01,
+    WARN_DELETE_FAILURE    = 0x02,
+    WARN_WRITE_FAILURE     = 0x03,
+    WARN_BUFFER_TOO_SMALL  = 0x04,
+    WARN_STALE_DATA        = 0x05,
+    WARN_FILE_SYSTEM       = 0x06,
+    WARN_RESET_REQUIRED    = 0x07,
+}
+
+impl EfiStatus {
+    /// Returns `true` if this is `EFI_SUCCESS`
+    pub fn is_success(self) -> bool {
+        self == EfiStatus::SUCCESS
+    }
+
+    /// Returns `true` if this status code is an error
+    pub fn is_error(self) -> bool {
+        (self.0 & ERROR_BIT) != 0
+    }
+
+    /// Returns `true` if this status code is a warning
+    pub fn is_warning(self) -> bool {
+        !self.is_success() && !self.is_error()
+    }
+
+    /// Convert the status into a `Result`. Warnings are not considered
+    /// failures and are turned into `Ok`.
+    pub fn into_result(self) -> EfiResult<()> {
+        if self.is_error() {
+            Err(EfiError(self))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl From<usize> for EfiStatus {
+    fn from(val: usize) -> Self {
+        EfiStatus(val)
+    }
+}
+
+impl From<EfiError> for EfiStatus {
+    fn from(err: EfiError) -> Self {
+        err.0
+    }
+}
+
+impl fmt::Debug for EfiStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None       => write!(f, "EfiStatus({:#x})", self.0),
+        }
+    }
+}
+
+impl fmt::Display for EfiStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// An error status code returned by the firmware
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct EfiError(pub(crate) EfiStatus);
+
+impl EfiError {
+    /// Get the status code of this error
+    pub fn status(self) -> EfiStatus {
+        self.0
+    }
+}
+
+impl fmt::Debug for EfiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for EfiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classification() {
+        assert!(EfiStatus::SUCCESS.is_success());
+        assert!(!EfiStatus::SUCCESS.is_error());
+        assert!(!EfiStatus::SUCCESS.is_warning());
+
+        assert!(EfiStatus::NOT_FOUND.is_error());
+        assert!(!EfiStatus::NOT_FOUND.is_warning());
+
+        assert!(EfiStatus::WARN_STALE_DATA.is_warning());
+        assert!(!EfiStatus::WARN_STALE_DATA.is_error());
+
+        // Unknown codes are classified by the error bit alone
+        assert!(EfiStatus(ERROR_BIT | 0x1234).is_error());
+        assert!(EfiStatus(0x1234).is_warning());
+    }
+
+    #[test]
+    fn into_result() {
+        assert_eq!(EfiStatus::SUCCESS.into_result(), Ok(()));
+        assert_eq!(EfiStatus::WARN_BUFFER_TOO_SMALL.into_result(), Ok(()));
+        assert_eq!(EfiStatus::BUFFER_TOO_SMALL.into_result(),
+            Err(EfiError(EfiStatus::BUFFER_TOO_SMALL)));
+    }
+}
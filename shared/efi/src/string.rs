@@ -0,0 +1,549 @@
+//! UCS-2 strings as used by the firmware for names, paths and messages
+//!
+//! UCS-2 is UTF-16 without surrogate pairs, thus only characters up to
+//! U+FFFF can be represented. Firmware strings are null terminated.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use crate::{EfiError, EfiStatus};
+
+/// Errors converting to UCS-2
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ucs2Error {
+    /// The character is above U+FFFF
+    ///
+    /// Like all indices in these errors, the index is the position in UCS-2
+    /// characters the character has or would have had in the encoded string.
+    Unrepresentable(char, usize),
+
+    /// A null character at the index, only the terminator may be null
+    InteriorNul(usize),
+
+    /// The UCS-2 characters are missing the null terminator
+    NotNulTerminated,
+
+    /// The string does not fit in the provided buffer
+    BufferTooSmall,
+}
+
+impl fmt::Display for Ucs2Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ucs2Error::Unrepresentable(chr, idx) =>
+                write!(f, "character {:?} at index {} does not fit in UCS-2",
+                    chr, idx),
+            Ucs2Error::InteriorNul(idx) =>
+                write!(f, "null character at index {}", idx),
+            Ucs2Error::NotNulTerminated =>
+                write!(f, "missing null terminator"),
+            Ucs2Error::BufferTooSmall =>
+                write!(f, "buffer too small"),
+        }
+    }
+}
+
+impl From<Ucs2Error> for EfiError {
+    fn from(err: Ucs2Error) -> Self {
+        match err {
+            Ucs2Error::BufferTooSmall =>
+                EfiError(EfiStatus::BUFFER_TOO_SMALL),
+            _ => EfiError(EfiStatus::INVALID_PARAMETER),
+        }
+    }
+}
+
+/// Encode `chr` as a UCS-2 character to be stored at index `idx`
+fn encode_char(chr: char, idx: usize) -> Result<u16, Ucs2Error> {
+    match chr as u32 {
+        0 => Err(Ucs2Error::InteriorNul(idx)),
+        code @ 1..=0xffff => Ok(code as u16),
+        _ => Err(Ucs2Error::Unrepresentable(chr, idx)),
+    }
+}
+
+/// Decode the UCS-2 character `chr`, replacing lone surrogates
+fn decode_char(chr: u16) -> char {
+    char::from_u32(chr as u32).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+/// Number of UCS-2 characters needed for `string`, used by `cstr16!`
+#[doc(hidden)]
+pub const fn ucs2_len(string: &str) -> usize {
+    let bytes = string.as_bytes();
+    let mut len = 0;
+    let mut idx = 0;
+
+    // Count every byte which starts a UTF-8 sequence
+    while idx < bytes.len() {
+        if bytes[idx] & 0xc0 != 0x80 { len += 1; }
+        idx += 1;
+    }
+
+    len
+}
+
+/// Encode `string` into a null terminated array of `N` UCS-2 characters at
+/// compile time, used by `cstr16!`
+#[doc(hidden)]
+pub const fn encode_ucs2<const N: usize>(string: &str) -> [u16; N] {
+    let bytes = string.as_bytes();
+    let mut chars = [0u16; N];
+    let mut idx = 0;
+    let mut len = 0;
+
+    while idx < bytes.len() {
+        let byte = bytes[idx] as u16;
+
+        // Decode one UTF-8 sequence, four byte sequences are above U+FFFF
+        let (chr, size) = if byte < 0x80 {
+            (byte, 1)
+        } else if byte < 0xe0 {
+            (((byte & 0x1f) << 6) | (bytes[idx + 1] as u16 & 0x3f), 2)
+        } else if byte < 0xf0 {
+            (((byte & 0x0f) << 12) | ((bytes[idx + 1] as u16 & 0x3f) << 6) |
+                (bytes[idx + 2] as u16 & 0x3f), 3)
+        } else {
+            panic!("character does not fit in UCS-2");
+        };
+
+        if chr == 0 { panic!("null character in string"); }
+
+        chars[len] = chr;
+        len += 1;
+        idx += size;
+    }
+
+    chars
+}
+
+/// A borrowed null terminated UCS-2 string
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct CStr16([u16]);
+
+impl CStr16 {
+    /// Wrap the null terminated string at `ptr`
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to a null terminated string which stays valid and
+    /// unmodified for `'a`.
+    pub unsafe fn from_ptr<'a>(ptr: *const u16) -> &'a CStr16 {
+        let mut len = 0;
+        while *ptr.add(len) != 0 { len += 1; }
+
+        Self::from_u16_with_nul_unchecked(
+            core::slice::from_raw_parts(ptr, len + 1))
+    }
+
+    /// Wrap `chars`, which must end with the only null character
+    pub fn from_u16_with_nul(chars: &[u16]) -> Result<&CStr16, Ucs2Error> {
+        match chars.iter().position(|&chr| chr == 0) {
+            Some(idx) if idx + 1 == chars.len() =>
+                Ok(unsafe { Self::from_u16_with_nul_unchecked(chars) }),
+            Some(idx) => Err(Ucs2Error::InteriorNul(idx)),
+            None      => Err(Ucs2Error::NotNulTerminated),
+        }
+    }
+
+    /// Wrap `chars` up to and including the first null character, ignoring
+    /// whatever follows it
+    pub fn from_u16_until_nul(chars: &[u16]) -> Result<&CStr16, Ucs2Error> {
+        let len = chars.iter().position(|&chr| chr == 0)
+            .ok_or(Ucs2Error::NotNulTerminated)?;
+
+        Ok(unsafe { Self::from_u16_with_nul_unchecked(&chars[..=len]) })
+    }
+
+    /// Wrap `chars` without checking them
+    ///
+    /// # Safety
+    ///
+    /// `chars` must end with the only null character.
+    pub const unsafe fn from_u16_with_nul_unchecked(chars: &[u16])
+            -> &CStr16 {
+        &*(chars as *const [u16] as *const CStr16)
+    }
+
+    /// Encode `string` into `buf` without allocating
+    pub fn from_str_with_buf<'a>(string: &str, buf: &'a mut [u16])
+            -> Result<&'a CStr16, Ucs2Error> {
+        let mut len = 0;
+
+        for chr in string.chars() {
+            // Leave room for the null terminator
+            if len + 1 >= buf.len() { return Err(Ucs2Error::BufferTooSmall); }
+
+            buf[len] = encode_char(chr, len)?;
+            len += 1;
+        }
+
+        if len >= buf.len() { return Err(Ucs2Error::BufferTooSmall); }
+        buf[len] = 0;
+
+        Ok(unsafe { Self::from_u16_with_nul_unchecked(&buf[..=len]) })
+    }
+
+    /// Encode as much of `string` as fits in `buf` without allocating,
+    /// replacing characters which don't fit in UCS-2 and null characters by
+    /// U+FFFD
+    ///
+    /// Returns the encoded string and the rest of `string` which didn't fit.
+    /// Panics if `buf` has no room for the null terminator.
+    pub fn from_str_lossy_with_buf<'a, 'b>(string: &'b str,
+            buf: &'a mut [u16]) -> (&'a CStr16, &'b str) {
+        let mut len = 0;
+        let mut end = string.len();
+
+        for (offset, chr) in string.char_indices() {
+            // Leave room for the null terminator
+            if len + 1 >= buf.len() {
+                end = offset;
+                break;
+            }
+
+            buf[len] = encode_char(chr, len).unwrap_or(0xfffd);
+            len += 1;
+        }
+
+        buf[len] = 0;
+
+        let encoded = unsafe {
+            Self::from_u16_with_nul_unchecked(&buf[..=len])
+        };
+
+        (encoded, &string[end..])
+    }
+
+    /// Get a pointer to the null terminated characters, to pass to the
+    /// firmware
+    pub fn as_ptr(&self) -> *const u16 {
+        self.0.as_ptr()
+    }
+
+    /// Get the characters without the null terminator
+    pub fn as_slice(&self) -> &[u16] {
+        &self.0[..self.0.len() - 1]
+    }
+
+    /// Get the characters including the null terminator
+    pub fn as_slice_with_nul(&self) -> &[u16] {
+        &self.0
+    }
+
+    /// Get the number of characters, without the null terminator
+    pub fn len(&self) -> usize {
+        self.0.len() - 1
+    }
+
+    /// Returns `true` if the string has no characters
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the size of the string in bytes, including the null terminator
+    pub fn size_with_nul(&self) -> usize {
+        self.0.len() * 2
+    }
+
+    /// Get an iterator over the characters of the string
+    ///
+    /// Surrogates, which are not valid in UCS-2, are replaced by U+FFFD
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.as_slice().iter().map(|&chr| decode_char(chr))
+    }
+
+    /// Copy the string into an owned `CString16`
+    pub fn to_cstring16(&self) -> CString16 {
+        CString16(self.0.to_vec())
+    }
+}
+
+impl fmt::Display for CStr16 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for chr in self.chars() {
+            fmt::Write::write_char(f, chr)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for CStr16 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"")?;
+
+        for chr in self.chars() {
+            write!(f, "{}", chr.escape_debug())?;
+        }
+
+        write!(f, "\"")
+    }
+}
+
+impl PartialEq<str> for CStr16 {
+    fn eq(&self, other: &str) -> bool {
+        self.chars().eq(other.chars())
+    }
+}
+
+impl PartialEq<&str> for CStr16 {
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
+
+impl PartialEq<CString16> for CStr16 {
+    fn eq(&self, other: &CString16) -> bool {
+        *self == **other
+    }
+}
+
+impl AsRef<CStr16> for CStr16 {
+    fn as_ref(&self) -> &CStr16 {
+        self
+    }
+}
+
+/// An owned null terminated UCS-2 string
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CString16(Vec<u16>);
+
+impl CString16 {
+    /// Create an empty string
+    pub fn new() -> Self {
+        CString16(alloc::vec![0])
+    }
+
+    /// Encode `string`, replacing characters which don't fit in UCS-2 and
+    /// null characters by U+FFFD
+    pub fn from_str_lossy(string: &str) -> Self {
+        let mut chars: Vec<u16> = string.chars()
+            .map(|chr| encode_char(chr, 0).unwrap_or(0xfffd))
+            .collect();
+        chars.push(0);
+
+        CString16(chars)
+    }
+
+    /// Take ownership of `chars`, which must end with the only null
+    /// character
+    pub fn from_vec_with_nul(chars: Vec<u16>) -> Result<Self, Ucs2Error> {
+        CStr16::from_u16_with_nul(&chars)?;
+        Ok(CString16(chars))
+    }
+
+    /// Append `chr` to the string
+    pub fn push(&mut self, chr: char) -> Result<(), Ucs2Error> {
+        let end = self.0.len() - 1;
+
+        self.0.insert(end, encode_char(chr, end)?);
+        Ok(())
+    }
+
+    /// Append `string` to the string, leaving it unchanged on errors
+    pub fn push_str(&mut self, string: &str) -> Result<(), Ucs2Error> {
+        let end = self.0.len() - 1;
+        let mut chars = Vec::with_capacity(string.len());
+
+        for chr in string.chars() {
+            chars.push(encode_char(chr, end + chars.len())?);
+        }
+
+        self.0.splice(end..end, chars);
+        Ok(())
+    }
+
+    /// Borrow the string as a `CStr16`
+    pub fn as_cstr16(&self) -> &CStr16 {
+        unsafe { CStr16::from_u16_with_nul_unchecked(&self.0) }
+    }
+}
+
+impl Default for CString16 {
+    fn default() -> Self {
+        CString16::new()
+    }
+}
+
+impl TryFrom<&str> for CString16 {
+    type Error = Ucs2Error;
+
+    fn try_from(string: &str) -> Result<Self, Self::Error> {
+        let mut string16 = CString16(Vec::with_capacity(string.len() + 1));
+
+        for chr in string.chars() {
+            string16.0.push(encode_char(chr, string16.0.len())?);
+        }
+
+        string16.0.push(0);
+        Ok(string16)
+    }
+}
+
+impl core::str::FromStr for CString16 {
+    type Err = Ucs2Error;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        CString16::try_from(string)
+    }
+}
+
+impl From<&CStr16> for CString16 {
+    fn from(string: &CStr16) -> Self {
+        string.to_cstring16()
+    }
+}
+
+impl From<&CStr16> for String {
+    fn from(string: &CStr16) -> Self {
+        string.chars().collect()
+    }
+}
+
+impl core::ops::Deref for CString16 {
+    type Target = CStr16;
+
+    fn deref(&self) -> &CStr16 {
+        self.as_cstr16()
+    }
+}
+
+impl core::borrow::Borrow<CStr16> for CString16 {
+    fn borrow(&self) -> &CStr16 {
+        self
+    }
+}
+
+impl AsRef<CStr16> for CString16 {
+    fn as_ref(&self) -> &CStr16 {
+        self
+    }
+}
+
+impl fmt::Display for CString16 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_cstr16(), f)
+    }
+}
+
+impl fmt::Debug for CString16 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_cstr16(), f)
+    }
+}
+
+impl PartialEq<str> for CString16 {
+    fn eq(&self, other: &str) -> bool {
+        *self.as_cstr16() == *other
+    }
+}
+
+impl PartialEq<&str> for CString16 {
+    fn eq(&self, other: &&str) -> bool {
+        *self.as_cstr16() == **other
+    }
+}
+
+impl PartialEq<CStr16> for CString16 {
+    fn eq(&self, other: &CStr16) -> bool {
+        *self.as_cstr16() == *other
+    }
+}
+
+impl PartialEq<&CStr16> for CString16 {
+    fn eq(&self, other: &&CStr16) -> bool {
+        *self.as_cstr16() == **other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn literal() {
+        let string = crate::cstr16!("h\u{e9}llo\\EFI");
+
+        assert_eq!(string.len(), 9);
+        assert_eq!(string.as_slice_with_nul().last(), Some(&0));
+        assert_eq!(string.size_with_nul(), 20);
+        assert_eq!(string, "h\u{e9}llo\\EFI");
+        assert!(crate::cstr16!("").is_empty());
+    }
+
+    #[test]
+    fn round_trip() {
+        let string = CString16::try_from("gem \u{2603}").unwrap();
+
+        assert_eq!(string.to_string(), "gem \u{2603}");
+        assert_eq!(string.as_slice(), &[0x67, 0x65, 0x6d, 0x20, 0x2603]);
+        assert_eq!(alloc::format!("{:?}", string), "\"gem \u{2603}\"");
+    }
+
+    #[test]
+    fn unrepresentable() {
+        assert_eq!(CString16::try_from("ab\u{1f600}"),
+            Err(Ucs2Error::Unrepresentable('\u{1f600}', 2)));
+
+        let mut buf = [0u16; 8];
+        assert_eq!(CStr16::from_str_with_buf("\u{1f600}", &mut buf),
+            Err(Ucs2Error::Unrepresentable('\u{1f600}', 0)));
+
+        assert_eq!(CString16::from_str_lossy("a\u{1f600}").as_slice(),
+            &[0x61, 0xfffd]);
+    }
+
+    #[test]
+    fn nul_handling() {
+        assert_eq!(CString16::try_from("a\0b"),
+            Err(Ucs2Error::InteriorNul(1)));
+        assert_eq!(CString16::try_from("\u{2603}\0"),
+            Err(Ucs2Error::InteriorNul(1)));
+
+        assert_eq!(CStr16::from_u16_with_nul(&[0x61, 0]).unwrap(), "a");
+        assert_eq!(CStr16::from_u16_with_nul(&[0x61, 0, 0x62, 0]),
+            Err(Ucs2Error::InteriorNul(1)));
+        assert_eq!(CStr16::from_u16_with_nul(&[0x61]),
+            Err(Ucs2Error::NotNulTerminated));
+
+        assert_eq!(CStr16::from_u16_until_nul(&[0x61, 0, 0x62]).unwrap(),
+            "a");
+        assert_eq!(CStr16::from_u16_until_nul(&[0x61]),
+            Err(Ucs2Error::NotNulTerminated));
+
+        assert_eq!(CString16::from_vec_with_nul(alloc::vec![0x61]),
+            Err(Ucs2Error::NotNulTerminated));
+    }
+
+    #[test]
+    fn with_buf() {
+        let mut buf = [0u16; 4];
+
+        assert_eq!(CStr16::from_str_with_buf("abc", &mut buf).unwrap(),
+            "abc");
+        assert_eq!(CStr16::from_str_with_buf("abcd", &mut buf),
+            Err(Ucs2Error::BufferTooSmall));
+
+        let (head, rest) = CStr16::from_str_lossy_with_buf("abcde", &mut buf);
+        assert_eq!(head, "abc");
+        assert_eq!(rest, "de");
+    }
+
+    #[test]
+    fn push() {
+        let mut string = CString16::new();
+
+        string.push('a').unwrap();
+        string.push_str("bc").unwrap();
+        assert_eq!(string, "abc");
+
+        // Errors report where the character would have ended up
+        assert_eq!(string.push('\u{1f600}'),
+            Err(Ucs2Error::Unrepresentable('\u{1f600}', 3)));
+        assert_eq!(string.push_str("d\0"), Err(Ucs2Error::InteriorNul(4)));
+        assert_eq!(string, "abc");
+    }
+}
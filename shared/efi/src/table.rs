@@ -0,0 +1,278 @@
+//! Validation of the table headers of the system, boot services and runtime
+//! services tables
+
+use core::fmt;
+use crate::{EfiBootServices, EfiError, EfiRuntimeServices, EfiStatus};
+use crate::{EfiSystemTable, EfiTableHeader};
+
+/// Signature of the EFI System Table, "IBI SYST"
+pub const EFI_SYSTEM_TABLE_SIGNATURE: u64 = 0x5453595320494249;
+
+/// Signature of the EFI Boot Services Table, "BOOTSERV"
+pub const EFI_BOOT_SERVICES_SIGNATURE: u64 = 0x56524553544f4f42;
+
+/// Signature of the EFI Runtime Services Table, "RUNTSERV"
+pub const EFI_RUNTIME_SERVICES_SIGNATURE: u64 = 0x56524553544e5552;
+
+/// Oldest specification revision gem runs on. The boot services we bind,
+/// up to `CreateEventEx()`, are only all there since UEFI 2.0.
+pub const MINIMUM_REVISION: Revision = Revision::new(2, 0);
+
+/// Largest table size we are willing to checksum, the standard tables are
+/// a few hundred bytes so anything bigger is a corrupt header
+const MAX_TABLE_SIZE: u32 = 64 * 1024;
+
+/// Lookup table of the CRC-32 used by the firmware, reflected polynomial
+/// 0xedb88320
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut idx = 0;
+
+    while idx < 256 {
+        let mut crc = idx as u32;
+        let mut bit = 0;
+
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+            bit += 1;
+        }
+
+        table[idx] = crc;
+        idx += 1;
+    }
+
+    table
+};
+
+/// Feed `data` into the inverted CRC-32 state `crc`
+fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    data.iter().fold(crc, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Compute the CRC-32 of `data`, the same as `CalculateCrc32()` does
+pub fn crc32(data: &[u8]) -> u32 {
+    !crc32_update(!0, data)
+}
+
+/// A UEFI specification revision as found in table headers
+///
+/// The upper 16 bits are the major revision, the lower 16 bits the minor
+/// revision as a decimal number from 0 to 99 holding two digits. Displayed
+/// as "2.3.1" for minor revision 31 and as "2.3" for minor revision 30.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Revision(pub u32);
+
+impl Revision {
+    /// Create the revision `major`.`minor`, with `minor` from 0 to 9
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Revision(((major as u32) << 16) | (minor as u32 * 10))
+    }
+
+    /// Get the major revision
+    pub fn major(&self) -> u16 {
+        (self.0 >> 16) as u16
+    }
+
+    /// Get the minor revision, including both digits: 31 for 2.3.1
+    pub fn minor(&self) -> u16 {
+        self.0 as u16
+    }
+}
+
+impl fmt::Display for Revision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let minor = self.minor();
+
+        write!(f, "{}.{}", self.major(), minor / 10)?;
+        if minor % 10 != 0 { write!(f, ".{}", minor % 10)?; }
+
+        Ok(())
+    }
+}
+
+/// Errors validating a firmware table, naming the table which failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableError {
+    /// The pointer to the table is null
+    Null(&'static str),
+
+    /// The table has the wrong signature
+    BadSignature(&'static str, u64),
+
+    /// The size in the header is too small for the table or implausibly
+    /// large
+    BadSize(&'static str, u32),
+
+    /// The CRC in the header does not match the contents
+    BadCrc { table: &'static str, expected: u32, computed: u32 },
+
+    /// The table conforms to a revision older than `MINIMUM_REVISION`
+    TooOld(&'static str, Revision),
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableError::Null(table) =>
+                write!(f, "{} table is missing", table),
+            TableError::BadSignature(table, signature) =>
+                write!(f, "{} table has a bad signature {:#018x}",
+                    table, signature),
+            TableError::BadSize(table, size) =>
+                write!(f, "{} table has a bad size of {} bytes", table, size),
+            TableError::BadCrc { table, expected, computed } =>
+                write!(f, "{} table is corrupt, CRC {:#010x} expected \
+                    {:#010x}", table, computed, expected),
+            TableError::TooOld(table, revision) =>
+                write!(f, "{} table revision {} is older than {}",
+                    table, revision, MINIMUM_REVISION),
+        }
+    }
+}
+
+impl From<TableError> for EfiError {
+    fn from(err: TableError) -> Self {
+        match err {
+            TableError::BadCrc { .. } => EfiError(EfiStatus::CRC_ERROR),
+            TableError::TooOld(..) =>
+                EfiError(EfiStatus::INCOMPATIBLE_VERSION),
+            _ => EfiError(EfiStatus::LOAD_ERROR),
+        }
+    }
+}
+
+impl EfiTableHeader {
+    /// Get the specification revision the table conforms to
+    pub fn revision(&self) -> Revision {
+        Revision(self.revision)
+    }
+}
+
+/// A firmware table starting with an `EfiTableHeader`
+///
+/// # Safety
+///
+/// The implementing type must match the layout of the table the firmware
+/// provides with `SIGNATURE`.
+pub unsafe trait Table {
+    /// The signature in the header of the table
+    const SIGNATURE: u64;
+
+    /// Name of the table for error messages
+    const NAME: &'static str;
+}
+
+unsafe impl Table for EfiSystemTable {
+    const SIGNATURE: u64 = EFI_SYSTEM_TABLE_SIGNATURE;
+    const NAME: &'static str = "System";
+}
+
+unsafe impl Table for EfiBootServices {
+    const SIGNATURE: u64 = EFI_BOOT_SERVICES_SIGNATURE;
+    const NAME: &'static str = "Boot services";
+}
+
+unsafe impl Table for EfiRuntimeServices {
+    const SIGNATURE: u64 = EFI_RUNTIME_SERVICES_SIGNATURE;
+    const NAME: &'static str = "Runtime services";
+}
+
+/// Check the signature, size and CRC of the table at `table`, returning the
+/// revision it conforms to
+///
+/// The table must be at least as large as `T`, so every field we access is
+/// provided by the firmware.
+///
+/// # Safety
+///
+/// `table` must either be null or point to readable memory of at least the
+/// size of an `EfiTableHeader`.
+pub unsafe fn validate_table<T: Table>(table: *const T)
+        -> Result<Revision, TableError> {
+    if table.is_null() { return Err(TableError::Null(T::NAME)); }
+
+    let header = &*(table as *const EfiTableHeader);
+
+    if header.signature != T::SIGNATURE {
+        return Err(TableError::BadSignature(T::NAME, header.signature));
+    }
+
+    if (header.header_size as usize) < core::mem::size_of::<T>() ||
+            header.header_size > MAX_TABLE_SIZE {
+        return Err(TableError::BadSize(T::NAME, header.header_size));
+    }
+
+    let bytes = core::slice::from_raw_parts(table as *const u8,
+        header.header_size as usize);
+
+    // The CRC is computed with the CRC field itself set to zero
+    let crc_offset = 16;
+    let crc_end    = crc_offset + core::mem::size_of::<u32>();
+
+    let crc = crc32_update(!0, &bytes[..crc_offset]);
+    let crc = crc32_update(crc, &[0; 4]);
+    let crc = !crc32_update(crc, &bytes[crc_end..]);
+
+    if crc != header.crc32 {
+        return Err(TableError::BadCrc {
+            table:    T::NAME,
+            expected: header.crc32,
+            computed: crc,
+        });
+    }
+
+    Ok(header.revision())
+}
+
+/// Validate the system table at `system_table` and the boot services and
+/// runtime services tables it points to
+///
+/// Returns the specification revision of the system table, which must not
+/// be older than `MINIMUM_REVISION`. This should be done before anything
+/// else touches the tables, the firmware hands them to us with valid CRCs.
+///
+/// # Safety
+///
+/// The pointers must either be null or point to readable memory, as is the
+/// case for what the firmware passes to the entry point.
+pub unsafe fn validate_system_table(system_table: *const EfiSystemTable)
+        -> Result<Revision, TableError> {
+    let revision = validate_table(system_table)?;
+
+    if revision < MINIMUM_REVISION {
+        return Err(TableError::TooOld(EfiSystemTable::NAME, revision));
+    }
+
+    validate_table((*system_table).boot_services)?;
+    validate_table((*system_table).runtime_services)?;
+
+    Ok(revision)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_known_vectors() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn revision_display() {
+        assert_eq!(alloc::format!("{}", Revision((2 << 16) | 30)), "2.3");
+        assert_eq!(alloc::format!("{}", Revision((2 << 16) | 31)), "2.3.1");
+        assert_eq!(alloc::format!("{}", Revision::new(1, 1)), "1.1");
+    }
+
+    #[test]
+    fn revision_order() {
+        assert!(Revision::new(1, 1) < MINIMUM_REVISION);
+        assert!(Revision((2 << 16) | 31) > Revision::new(2, 3));
+        assert_eq!(Revision((2 << 16) | 31).major(), 2);
+        assert_eq!(Revision((2 << 16) | 31).minor(), 31);
+    }
+}
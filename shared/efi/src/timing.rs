@@ -0,0 +1,66 @@
+//! Watchdog timer, busy waiting and the platform's monotonic counter
+
+use core::time::Duration;
+use crate::{boot_services, EfiError, EfiResult, EfiStatus};
+
+/// Largest watchdog code reserved for the firmware
+pub const MAX_FIRMWARE_WATCHDOG_CODE: u64 = 0xffff;
+
+/// Watchdog code logged when a watchdog armed by gem expires
+pub const WATCHDOG_CODE: u64 = 0x10000;
+
+/// Arm the watchdog timer, resetting the system if it isn't rearmed or
+/// disabled within `timeout`
+///
+/// `timeout` is rounded up to whole seconds. `code` is logged by the
+/// firmware when the watchdog expires and must be above
+/// `MAX_FIRMWARE_WATCHDOG_CODE`. The watchdog only runs while boot services
+/// are available, `exit_boot_services()` disables it.
+pub fn set_watchdog(timeout: Duration, code: u64) -> EfiResult<()> {
+    if code <= MAX_FIRMWARE_WATCHDOG_CODE {
+        return Err(EfiError(EfiStatus::INVALID_PARAMETER));
+    }
+
+    // A timeout of 0 would disable the watchdog instead
+    let seconds = timeout.as_secs() +
+        if timeout.subsec_nanos() != 0 { 1 } else { 0 };
+    let seconds = seconds.max(1) as usize;
+
+    unsafe {
+        (boot_services()?.set_watchdog_timer)(seconds, code, 0,
+            core::ptr::null())
+    }.into_result()
+}
+
+/// Disable the watchdog timer
+///
+/// The firmware arms a 5 minute watchdog before starting a boot option,
+/// this must be called by anything running longer than that.
+pub fn disable_watchdog() -> EfiResult<()> {
+    unsafe {
+        (boot_services()?.set_watchdog_timer)(0, 0, 0, core::ptr::null())
+    }.into_result()
+}
+
+/// Busy wait for at least `duration`, with microsecond granularity
+///
+/// Prefer `sleep()` for longer waits, it lets the firmware idle
+pub fn stall(duration: Duration) -> EfiResult<()> {
+    let micros = duration.as_micros().min(usize::MAX as u128) as usize;
+
+    unsafe { (boot_services()?.stall)(micros) }.into_result()
+}
+
+/// Get the next value of the platform's monotonic counter
+///
+/// The counter increases on every call and never repeats, not even across
+/// reboots. It is not related to time.
+pub fn monotonic_count() -> EfiResult<u64> {
+    let mut count = 0;
+
+    unsafe {
+        (boot_services()?.get_next_monotonic_count)(&mut count)
+    }.into_result()?;
+
+    Ok(count)
+}